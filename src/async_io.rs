@@ -0,0 +1,252 @@
+use std::io::{self, SeekFrom};
+use std::mem::size_of;
+
+use futures_util::io::{
+    AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt,
+};
+use itertools::izip;
+
+use crate::decoder::Inflate;
+use crate::endsley::{parse_endsley, ENDSLEY_MAGIC};
+use crate::{
+    check_block_size, decode_control_block, read_block_to_end, read_header, ApplyState, Block,
+    Bsdiff4, Compression, Error, Header, Limits, Result, CHUNK_SIZE, MAGIC,
+};
+
+/// Async counterparts of [`Bsdiff4::read`] and [`Bsdiff4::apply`] on the
+/// `futures-io` traits. Tokio types can be adapted with `tokio_util::compat`.
+impl Bsdiff4 {
+    /// Reads a patch from an async stream.
+    ///
+    /// Raw and bzip2 blocks are decoded chunk by chunk as their bytes arrive.
+    /// Blocks in other codecs are buffered and then decoded in one go, which
+    /// blocks the task for as long as decompression takes.
+    pub async fn read_async<R: AsyncRead + Unpin>(r: &mut R) -> Result<Self> {
+        Self::read_async_with_limits(r, &Limits::default()).await
+    }
+
+    pub async fn read_async_with_limits<R: AsyncRead + Unpin>(
+        r: &mut R,
+        limits: &Limits,
+    ) -> Result<Self> {
+        let header = read_header(&mut read_header_async(r).await?.as_slice())?;
+        let new_size = header.checked_new_size(limits)?;
+
+        let patch = match header {
+            Header::Blocks {
+                compression: [control_compression, diff_compression, extra_compression],
+                len_control,
+                len_diff,
+                ..
+            } => {
+                let control = read_block_async(
+                    r,
+                    Some(len_control),
+                    control_compression,
+                    Block::Control,
+                    limits,
+                )
+                .await?;
+                let control = decode_control_block(&control, limits)?;
+                let diff =
+                    read_block_async(r, Some(len_diff), diff_compression, Block::Diff, limits)
+                        .await?;
+                let extra =
+                    read_block_async(r, None, extra_compression, Block::Extra, limits).await?;
+
+                Bsdiff4 {
+                    new_size,
+                    control,
+                    diff,
+                    extra,
+                }
+            }
+            Header::Endsley { .. } => {
+                let stream =
+                    read_block_async(r, None, Compression::Bzip2, Block::Interleaved, limits)
+                        .await?;
+
+                parse_endsley(&stream, new_size, limits)?
+            }
+        };
+
+        patch.validate()?;
+
+        Ok(patch)
+    }
+
+    pub async fn apply_async<R, W>(&self, original: &mut R, new: &mut W) -> Result<()>
+    where
+        R: AsyncRead + AsyncSeek + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut diff = self.diff.as_slice();
+        let mut extra = self.extra.as_slice();
+        let state = &mut ApplyState::new(original.stream_position().await?);
+
+        for control in &self.control {
+            let mut remaining = control.diff_amount;
+
+            while remaining > 0 {
+                let len = remaining.min(CHUNK_SIZE as u64) as usize;
+                let new_chunk = &mut state.original_chunk[..len];
+
+                if read_full_async(original, new_chunk).await? < len {
+                    return Err(Error::OriginalTooShort {
+                        control: state.control,
+                        original_offset: state.original_offset,
+                    });
+                }
+
+                if diff.len() < len {
+                    return Err(state.block_too_short(Block::Diff));
+                }
+
+                let (diff_chunk, rest) = diff.split_at(len);
+
+                for (orig, diff) in izip!(new_chunk.iter_mut(), diff_chunk) {
+                    *orig = orig.wrapping_add(*diff);
+                }
+
+                new.write_all(new_chunk).await?;
+
+                diff = rest;
+                remaining -= len as u64;
+                state.original_offset += len as u64;
+                state.new_offset += len as u64;
+            }
+
+            let len = usize::try_from(control.extra_amount)
+                .ok()
+                .filter(|&len| len <= extra.len())
+                .ok_or_else(|| state.block_too_short(Block::Extra))?;
+            let (extra_chunk, rest) = extra.split_at(len);
+
+            new.write_all(extra_chunk).await?;
+
+            extra = rest;
+            state.new_offset += len as u64;
+
+            if state
+                .original_offset
+                .checked_add_signed(control.seek)
+                .is_none()
+            {
+                return Err(Error::InvalidSeek {
+                    control: state.control,
+                    original_offset: state.original_offset,
+                    seek: control.seek,
+                });
+            }
+
+            state.original_offset = original.seek(SeekFrom::Current(control.seek)).await?;
+            state.control += 1;
+        }
+
+        Ok(())
+    }
+}
+
+async fn read_full_async<R: AsyncRead + Unpin>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]).await {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Reads the magic and the length fields that follow it.
+async fn read_header_async<R: AsyncRead + Unpin>(r: &mut R) -> Result<Vec<u8>> {
+    let mut header = vec![0; MAGIC.len()];
+    read_header_exact_async(r, &mut header).await?;
+
+    let len = if ENDSLEY_MAGIC.starts_with(&header) {
+        ENDSLEY_MAGIC.len() + size_of::<u64>()
+    } else {
+        MAGIC.len() + 3 * size_of::<u64>()
+    };
+
+    header.resize(len, 0);
+    read_header_exact_async(r, &mut header[MAGIC.len()..]).await?;
+
+    Ok(header)
+}
+
+async fn read_header_exact_async<R: AsyncRead + Unpin>(r: &mut R, buf: &mut [u8]) -> Result<()> {
+    if read_full_async(r, buf).await? < buf.len() {
+        return Err(Error::TruncatedHeader);
+    }
+
+    Ok(())
+}
+
+/// Reads a block of `len` compressed bytes, or up to the end of the stream,
+/// decoding each chunk as it arrives where the codec allows it.
+async fn read_block_async<R: AsyncRead + Unpin>(
+    r: &mut R,
+    len: Option<u64>,
+    compression: Compression,
+    block: Block,
+    limits: &Limits,
+) -> Result<Vec<u8>> {
+    let mut remaining = len.unwrap_or(u64::MAX);
+
+    let Ok(mut inflate) = Inflate::new(compression) else {
+        let mut compressed = Vec::new();
+        (&mut *r)
+            .take(remaining)
+            .read_to_end(&mut compressed)
+            .await?;
+
+        return read_block_to_end(&mut compressed.as_slice(), compression, block, limits);
+    };
+
+    let mut input = vec![0; CHUNK_SIZE];
+    let mut data = Vec::new();
+    let mut compressed = 0;
+    let mut done = false;
+
+    while remaining > 0 {
+        let want = remaining.min(CHUNK_SIZE as u64) as usize;
+        let n = read_full_async(r, &mut input[..want]).await?;
+
+        if n == 0 {
+            break;
+        }
+
+        remaining -= n as u64;
+        compressed += n as u64;
+
+        let mut chunk = &input[..n];
+
+        while !done {
+            let start = data.len();
+            data.resize(start + CHUNK_SIZE, 0);
+
+            let (read, written, end) = inflate.decode(chunk, &mut data[start..], block)?;
+
+            data.truncate(start + written);
+            chunk = &chunk[read..];
+            done = end;
+
+            check_block_size(block, data.len() as u64, compressed, limits)?;
+
+            if read == 0 && written == 0 {
+                break;
+            }
+        }
+    }
+
+    if !done && inflate.is_framed() {
+        return Err(Error::TruncatedBlock { block });
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,142 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::process::ExitCode;
+
+use bsdiff4_rs::Bsdiff4;
+
+const USAGE: &str =
+    "usage: bsdiff [--level <1-9>] [--memory-limit <bytes>[k|M|G]] oldfile newfile patchfile";
+
+const EXIT_USAGE: u8 = 1;
+const EXIT_FAILURE: u8 = 2;
+
+struct Args {
+    old: OsString,
+    new: OsString,
+    patch: OsString,
+    level: u32,
+    memory_limit: Option<u64>,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args_os().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("bsdiff: {err}\n{USAGE}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("bsdiff: {err}");
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = OsString>) -> Result<Args, String> {
+    let mut paths = Vec::new();
+    let mut level = 9;
+    let mut memory_limit = None;
+
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--level") => {
+                let value = option_value(&mut args, "--level")?;
+
+                level = value
+                    .parse()
+                    .ok()
+                    .filter(|level| (1..=9).contains(level))
+                    .ok_or_else(|| format!("invalid compression level `{value}`"))?;
+            }
+            Some("--memory-limit") => {
+                let value = option_value(&mut args, "--memory-limit")?;
+
+                memory_limit = Some(
+                    parse_size(&value).ok_or_else(|| format!("invalid memory limit `{value}`"))?,
+                );
+            }
+            Some("--") => {
+                paths.extend(args.by_ref());
+            }
+            Some(flag) if flag.starts_with("--") => {
+                return Err(format!("unknown option `{flag}`"));
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    let [old, new, patch] = <[OsString; 3]>::try_from(paths)
+        .map_err(|paths| format!("expected 3 paths, got {}", paths.len()))?;
+
+    Ok(Args {
+        old,
+        new,
+        patch,
+        level,
+        memory_limit,
+    })
+}
+
+fn option_value(args: &mut impl Iterator<Item = OsString>, name: &str) -> Result<String, String> {
+    args.next()
+        .and_then(|value| value.into_string().ok())
+        .ok_or_else(|| format!("missing value for `{name}`"))
+}
+
+fn parse_size(size: &str) -> Option<u64> {
+    let (digits, multiplier) = match size.as_bytes().last()? {
+        b'k' | b'K' => (&size[..size.len() - 1], 1 << 10),
+        b'm' | b'M' => (&size[..size.len() - 1], 1 << 20),
+        b'g' | b'G' => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(memory_limit) = args.memory_limit {
+        let old_len = fs::metadata(&args.old)?.len();
+        let new_len = fs::metadata(&args.new)?.len();
+        let estimate = Bsdiff4::diff_memory_estimate(old_len as usize, new_len as usize);
+
+        if estimate > memory_limit {
+            return Err(format!(
+                "diffing needs about {estimate} bytes of memory, over the limit of {memory_limit}"
+            )
+            .into());
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    let patch = Bsdiff4::diff_mmap(&args.old, &args.new)?;
+    #[cfg(not(feature = "mmap"))]
+    let patch = Bsdiff4::diff(&fs::read(&args.old)?, &fs::read(&args.new)?);
+
+    let mut encoded = Vec::new();
+    patch.write_with_level(&mut encoded, args.level)?;
+
+    let mut out = BufWriter::new(File::create(&args.patch)?);
+    out.write_all(&encoded)?;
+    out.flush()?;
+
+    let ratio = if patch.new_size() == 0 {
+        0.0
+    } else {
+        encoded.len() as f64 / patch.new_size() as f64
+    };
+
+    println!("controls:   {}", patch.control_count());
+    println!("diff:       {} bytes", patch.diff_len());
+    println!("extra:      {} bytes", patch.extra_len());
+    println!("patch:      {} bytes", encoded.len());
+    println!("ratio:      {:.2}%", ratio * 100.0);
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read, Seek, Write};
+use std::process::ExitCode;
+
+#[cfg(feature = "checksum")]
+use bsdiff4_rs::checksum::Checksum;
+use bsdiff4_rs::{Bsdiff4, Error};
+
+#[cfg(feature = "checksum")]
+const USAGE: &str = "usage: bspatch [--verify <checksum>] oldfile newfile patchfile";
+#[cfg(not(feature = "checksum"))]
+const USAGE: &str = "usage: bspatch oldfile newfile patchfile";
+
+const EXIT_USAGE: u8 = 1;
+const EXIT_BAD_PATCH: u8 = 2;
+const EXIT_BAD_ORIGINAL: u8 = 3;
+const EXIT_IO: u8 = 4;
+
+struct Args {
+    old: OsString,
+    new: OsString,
+    patch: OsString,
+    #[cfg(feature = "checksum")]
+    verify: Option<Checksum>,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args_os().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("bspatch: {err}\n{USAGE}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("bspatch: {err}");
+            ExitCode::from(exit_code(&err))
+        }
+    }
+}
+
+fn parse_args(args: impl Iterator<Item = OsString>) -> Result<Args, String> {
+    let mut args = args;
+    let mut paths = Vec::new();
+    #[cfg(feature = "checksum")]
+    let mut verify = None;
+
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            #[cfg(feature = "checksum")]
+            Some("--verify") => {
+                let checksum = args.next().ok_or("missing value for `--verify`")?;
+                let checksum = checksum.to_str().ok_or("invalid checksum")?;
+                let checksum = checksum.parse().map_err(|err| format!("{err}"))?;
+
+                verify = Some(checksum);
+            }
+            Some("--") => {
+                paths.extend(args.by_ref());
+            }
+            Some(flag) if flag.starts_with("--") => {
+                return Err(format!("unknown option `{flag}`"));
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    let [old, new, patch] = <[OsString; 3]>::try_from(paths)
+        .map_err(|paths| format!("expected 3 paths, got {}", paths.len()))?;
+
+    if old == "-" && patch == "-" {
+        return Err("only one of oldfile and patchfile can be `-`".to_owned());
+    }
+
+    Ok(Args {
+        old,
+        new,
+        patch,
+        #[cfg(feature = "checksum")]
+        verify,
+    })
+}
+
+fn run(args: &Args) -> Result<(), Error> {
+    let patch = {
+        let mut patch = open_input(&args.patch)?;
+
+        Bsdiff4::read(&mut patch)?
+    };
+
+    // The new file is only written once the patch applied, so that it may
+    // name the old file.
+    let mut old = open_original(&args.old)?;
+    let mut new = Vec::new();
+
+    #[cfg(feature = "checksum")]
+    if let Some(checksum) = &args.verify {
+        patch.apply_verified(&mut old, &mut new, checksum)?;
+    } else {
+        patch.apply(&mut old, &mut new)?;
+    }
+    #[cfg(not(feature = "checksum"))]
+    patch.apply(&mut old, &mut new)?;
+
+    drop(old);
+    write_output(&args.new, &new)?;
+
+    Ok(())
+}
+
+fn open_input(path: &OsString) -> io::Result<Box<dyn Read>> {
+    if path == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+fn open_original(path: &OsString) -> io::Result<Box<dyn ReadSeek>> {
+    if path == "-" {
+        let mut original = Vec::new();
+        io::stdin().lock().read_to_end(&mut original)?;
+
+        return Ok(Box::new(Cursor::new(original)));
+    }
+
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+fn write_output(path: &OsString, new: &[u8]) -> io::Result<()> {
+    if path == "-" {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(new)?;
+
+        return stdout.flush();
+    }
+
+    fs::write(path, new)
+}
+
+fn exit_code(err: &Error) -> u8 {
+    match err {
+        Error::Io(_) => EXIT_IO,
+        Error::OriginalTooShort { .. } => EXIT_BAD_ORIGINAL,
+        #[cfg(feature = "checksum")]
+        Error::ChecksumMismatch { .. } => EXIT_BAD_ORIGINAL,
+        _ => EXIT_BAD_PATCH,
+    }
+}
@@ -1,17 +1,161 @@
 use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
 
-use md5::{Digest, Md5};
 use serde::de::Error;
 use serde::Deserialize;
 
-#[derive(Clone)]
-pub struct Checksum([u8; 16]);
+#[cfg(not(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha256",
+    feature = "blake3",
+    feature = "crc32"
+)))]
+compile_error!(
+    "the `checksum` feature needs at least one of `md5`, `sha1`, `sha256`, `blake3` or `crc32`"
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Algorithm {
+    #[cfg(feature = "md5")]
+    Md5,
+    #[cfg(feature = "sha1")]
+    Sha1,
+    #[cfg(feature = "sha256")]
+    Sha256,
+    #[cfg(feature = "blake3")]
+    Blake3,
+    #[cfg(feature = "crc32")]
+    Crc32,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "md5")]
+            Algorithm::Md5 => "md5",
+            #[cfg(feature = "sha1")]
+            Algorithm::Sha1 => "sha1",
+            #[cfg(feature = "sha256")]
+            Algorithm::Sha256 => "sha256",
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => "blake3",
+            #[cfg(feature = "crc32")]
+            Algorithm::Crc32 => "crc32",
+        }
+    }
+
+    pub fn digest_len(self) -> usize {
+        match self {
+            #[cfg(feature = "md5")]
+            Algorithm::Md5 => 16,
+            #[cfg(feature = "sha1")]
+            Algorithm::Sha1 => 20,
+            #[cfg(feature = "sha256")]
+            Algorithm::Sha256 => 32,
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => 32,
+            #[cfg(feature = "crc32")]
+            Algorithm::Crc32 => 4,
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = ParseChecksumError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            #[cfg(feature = "md5")]
+            "md5" => Ok(Algorithm::Md5),
+            #[cfg(feature = "sha1")]
+            "sha1" => Ok(Algorithm::Sha1),
+            #[cfg(feature = "sha256")]
+            "sha256" => Ok(Algorithm::Sha256),
+            #[cfg(feature = "blake3")]
+            "blake3" => Ok(Algorithm::Blake3),
+            #[cfg(feature = "crc32")]
+            "crc32" => Ok(Algorithm::Crc32),
+            _ => Err(ParseChecksumError::UnknownAlgorithm(name.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseChecksumError {
+    #[error("unknown checksum algorithm `{0}`")]
+    UnknownAlgorithm(String),
+    #[error("invalid checksum: {0}")]
+    InvalidHex(#[from] hex::FromHexError),
+    #[error("invalid checksum size {len} for {algorithm}")]
+    InvalidLength { algorithm: Algorithm, len: usize },
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Checksum {
+    algorithm: Algorithm,
+    digest: Box<[u8]>,
+}
 
 impl Checksum {
+    pub fn new(algorithm: Algorithm, digest: &[u8]) -> Result<Self, ParseChecksumError> {
+        if digest.len() != algorithm.digest_len() {
+            return Err(ParseChecksumError::InvalidLength {
+                algorithm,
+                len: digest.len(),
+            });
+        }
+
+        Ok(Checksum {
+            algorithm,
+            digest: digest.into(),
+        })
+    }
+
+    pub fn compute(algorithm: Algorithm, bytes: impl AsRef<[u8]>) -> Self {
+        let mut hasher = Hasher::new(algorithm);
+        hasher.update(bytes.as_ref());
+
+        hasher.finish()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
     pub fn validate_bytes(&self, bytes: impl AsRef<[u8]>) -> bool {
-        let checksum = Md5::digest(bytes);
+        let checksum = Checksum::compute(self.algorithm, bytes);
+
+        checksum == *self
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = ParseChecksumError;
 
-        checksum.as_slice() == self.0
+    /// Parses `<algorithm>:<hex>`, or with the `md5` feature a bare 32 digit hex
+    /// string as MD5.
+    fn from_str(checksum: &str) -> Result<Self, Self::Err> {
+        let (algorithm, digest) = match checksum.split_once(':') {
+            Some((algorithm, digest)) => (algorithm, digest),
+            None => ("md5", checksum),
+        };
+        let algorithm = algorithm.parse()?;
+        let digest = hex::decode(digest)?;
+
+        Checksum::new(algorithm, &digest)
     }
 }
 
@@ -21,18 +165,127 @@ impl<'de> Deserialize<'de> for Checksum {
         D: serde::Deserializer<'de>,
     {
         let checksum = String::deserialize(de)?;
-        let checksum = hex::decode(checksum).map_err(D::Error::custom)?;
-        let checksum = <[u8; 16]>::try_from(checksum)
-            .map_err(|_| D::Error::custom("invalid checksum size"))?;
+        let checksum = checksum.parse().map_err(D::Error::custom)?;
 
-        Ok(Checksum(checksum))
+        Ok(checksum)
     }
 }
 
 impl fmt::Debug for Checksum {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("Checksum")
-            .field(&hex::encode(self.0))
+            .field(&format_args!("{self}"))
             .finish()
     }
 }
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, hex::encode(&self.digest))
+    }
+}
+
+enum Hasher {
+    #[cfg(feature = "md5")]
+    Md5(md5::Md5),
+    #[cfg(feature = "sha1")]
+    Sha1(sha1::Sha1),
+    #[cfg(feature = "sha256")]
+    Sha256(sha2::Sha256),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+    #[cfg(feature = "crc32")]
+    Crc32(crc32fast::Hasher),
+}
+
+impl Hasher {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            #[cfg(feature = "md5")]
+            Algorithm::Md5 => Hasher::Md5(md5::Digest::new()),
+            #[cfg(feature = "sha1")]
+            Algorithm::Sha1 => Hasher::Sha1(sha1::Digest::new()),
+            #[cfg(feature = "sha256")]
+            Algorithm::Sha256 => Hasher::Sha256(sha2::Digest::new()),
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => Hasher::Blake3(Box::default()),
+            #[cfg(feature = "crc32")]
+            Algorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match *self {
+            #[cfg(feature = "md5")]
+            Hasher::Md5(ref mut hasher) => md5::Digest::update(hasher, bytes),
+            #[cfg(feature = "sha1")]
+            Hasher::Sha1(ref mut hasher) => sha1::Digest::update(hasher, bytes),
+            #[cfg(feature = "sha256")]
+            Hasher::Sha256(ref mut hasher) => sha2::Digest::update(hasher, bytes),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(ref mut hasher) => {
+                hasher.update(bytes);
+            }
+            #[cfg(feature = "crc32")]
+            Hasher::Crc32(ref mut hasher) => hasher.update(bytes),
+        }
+    }
+
+    fn finish(self) -> Checksum {
+        let (algorithm, digest): (_, Box<[u8]>) = match self {
+            #[cfg(feature = "md5")]
+            Hasher::Md5(hasher) => (
+                Algorithm::Md5,
+                md5::Digest::finalize(hasher).to_vec().into(),
+            ),
+            #[cfg(feature = "sha1")]
+            Hasher::Sha1(hasher) => (
+                Algorithm::Sha1,
+                sha1::Digest::finalize(hasher).to_vec().into(),
+            ),
+            #[cfg(feature = "sha256")]
+            Hasher::Sha256(hasher) => (
+                Algorithm::Sha256,
+                sha2::Digest::finalize(hasher).to_vec().into(),
+            ),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(hasher) => (Algorithm::Blake3, hasher.finalize().as_bytes()[..].into()),
+            #[cfg(feature = "crc32")]
+            Hasher::Crc32(hasher) => (Algorithm::Crc32, hasher.finalize().to_be_bytes()[..].into()),
+        };
+
+        Checksum { algorithm, digest }
+    }
+}
+
+pub(crate) struct HashingWriter<W> {
+    writer: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub(crate) fn new(writer: W, algorithm: Algorithm) -> Self {
+        HashingWriter {
+            writer,
+            hasher: Hasher::new(algorithm),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Checksum {
+        self.hasher.finish()
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes_written = self.writer.write(buf)?;
+
+        self.hasher.update(&buf[..bytes_written]);
+
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
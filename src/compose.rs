@@ -0,0 +1,157 @@
+use alloc::vec::Vec;
+
+use itertools::izip;
+
+use crate::{Bsdiff4, Control, ControlEntry, Error, Result};
+
+impl Bsdiff4 {
+    /// Combines a patch from A to B and a patch from B to C into a single
+    /// patch from A to C, without needing B.
+    ///
+    /// Bytes `bc` takes from B are traced back through `ab`: those `ab` builds
+    /// from A stay diff bytes against A, and those it takes from its extra
+    /// block become extra bytes of the result.
+    pub fn compose(ab: &Bsdiff4, bc: &Bsdiff4) -> Result<Self> {
+        let index: Vec<ControlEntry<'_>> = ab
+            .controls()
+            .filter(|entry| !entry.diff().is_empty() || !entry.extra().is_empty())
+            .collect();
+        let mut composer = Composer::new();
+
+        for entry in bc.controls() {
+            let mut b_offset = entry.original_offset();
+            let mut bc_diff = entry.diff();
+
+            while !bc_diff.is_empty() {
+                if b_offset >= ab.new_size as u64 {
+                    return Err(Error::OriginalTooShort {
+                        control: entry.index(),
+                        original_offset: b_offset,
+                    });
+                }
+
+                let ab_entry = index[index.partition_point(|ab| ab.new_offset() <= b_offset) - 1];
+                let offset = (b_offset - ab_entry.new_offset()) as usize;
+
+                let len = if offset < ab_entry.diff().len() {
+                    let ab_diff = &ab_entry.diff()[offset..];
+                    let len = ab_diff.len().min(bc_diff.len());
+
+                    composer.push_diff(
+                        ab_entry.original_offset() + offset as u64,
+                        izip!(&ab_diff[..len], &bc_diff[..len])
+                            .map(|(ab, bc)| ab.wrapping_add(*bc)),
+                    );
+
+                    len
+                } else {
+                    let ab_extra = &ab_entry.extra()[offset - ab_entry.diff().len()..];
+                    let len = ab_extra.len().min(bc_diff.len());
+
+                    composer.push_extra(
+                        izip!(&ab_extra[..len], &bc_diff[..len])
+                            .map(|(ab, bc)| ab.wrapping_add(*bc)),
+                    );
+
+                    len
+                };
+
+                bc_diff = &bc_diff[len..];
+                b_offset += len as u64;
+            }
+
+            composer.push_extra(entry.extra().iter().copied());
+        }
+
+        let (control, diff, extra) = composer.finish();
+
+        Bsdiff4::from_parts(bc.new_size, control, diff, extra)
+    }
+}
+
+/// Builds a control stream from runs of diff and extra bytes, merging runs
+/// that continue each other.
+pub(crate) struct Composer {
+    control: Vec<Control>,
+    diff: Vec<u8>,
+    extra: Vec<u8>,
+    current: Control,
+    original_offset: u64,
+}
+
+impl Composer {
+    pub(crate) fn new() -> Self {
+        Composer {
+            control: Vec::new(),
+            diff: Vec::new(),
+            extra: Vec::new(),
+            current: Control::new(0, 0, 0),
+            original_offset: 0,
+        }
+    }
+
+    pub(crate) fn push_diff(&mut self, original_offset: u64, diff: impl Iterator<Item = u8>) {
+        let len = self.diff.len();
+        self.diff.extend(diff);
+        let len = (self.diff.len() - len) as u64;
+
+        if self.current.extra_amount > 0 || original_offset != self.original_offset {
+            self.current.seek = original_offset.wrapping_sub(self.original_offset) as i64;
+            self.control.push(self.current);
+            self.current = Control::new(0, 0, 0);
+        }
+
+        self.current.diff_amount += len;
+        self.original_offset = original_offset + len;
+    }
+
+    pub(crate) fn push_extra(&mut self, extra: impl Iterator<Item = u8>) {
+        let len = self.extra.len();
+        self.extra.extend(extra);
+
+        self.current.extra_amount += (self.extra.len() - len) as u64;
+    }
+
+    pub(crate) fn finish(mut self) -> (Vec<Control>, Vec<u8>, Vec<u8>) {
+        if self.current != Control::new(0, 0, 0) || self.control.is_empty() {
+            self.control.push(self.current);
+        }
+
+        (self.control, self.diff, self.extra)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::diff::tests::{edit, sample};
+    use crate::{Bsdiff4, Control, Error};
+
+    #[test]
+    fn compose_round_trip() {
+        let a = sample(20_000, 5);
+        let b = edit(&a, 6);
+        let c = edit(&b, 7);
+
+        for (a, b, c) in [(&a, &b, &c), (&c, &b, &a), (&a, &c, &a)] {
+            let ac = Bsdiff4::compose(&Bsdiff4::diff(a, b), &Bsdiff4::diff(b, c)).unwrap();
+
+            assert_eq!(ac.apply_to_slice(a).unwrap(), *c);
+        }
+    }
+
+    #[test]
+    fn compose_reads_past_intermediate() {
+        let ab = Bsdiff4::from_parts(2, vec![Control::new(0, 2, 0)], vec![], vec![1, 2]).unwrap();
+        let bc = Bsdiff4::from_parts(3, vec![Control::new(3, 0, 0)], vec![0; 3], vec![]).unwrap();
+
+        assert!(matches!(
+            Bsdiff4::compose(&ab, &bc),
+            Err(Error::OriginalTooShort {
+                control: 0,
+                original_offset: 2
+            })
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::io::{self, Read};
+use crate::{Error, Result};
+
+/// Compression applied to a single block of a patch.
+///
+/// Every variant can be named in a patch header, but decoding or encoding one
+/// needs its cargo feature (`bzip2`, `brotli`, `zstd`, `xz` or `lz4`); raw
+/// storage is always available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Raw,
+    Bzip2,
+    Brotli,
+    Zstd,
+    Xz,
+    Lz4,
+}
+
+impl Compression {
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Raw => "raw",
+            Compression::Bzip2 => "bzip2",
+            Compression::Brotli => "brotli",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    pub(crate) fn from_bsdf2(id: u8) -> Result<Self> {
+        match id {
+            0..=2 => Compression::from_id(id),
+            _ => Err(Error::UnknownCompression { id }),
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Compression::Raw),
+            1 => Ok(Compression::Bzip2),
+            2 => Ok(Compression::Brotli),
+            3 => Ok(Compression::Zstd),
+            4 => Ok(Compression::Xz),
+            5 => Ok(Compression::Lz4),
+            _ => Err(Error::UnknownCompression { id }),
+        }
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Compression::Raw => 0,
+            Compression::Bzip2 => 1,
+            Compression::Brotli => 2,
+            Compression::Zstd => 3,
+            Compression::Xz => 4,
+            Compression::Lz4 => 5,
+        }
+    }
+
+    fn level(self, level: Option<u32>) -> Result<u32> {
+        let (default, range) = match self {
+            Compression::Raw | Compression::Lz4 => (0, 0..=0),
+            Compression::Bzip2 => (9, 1..=9),
+            Compression::Brotli => (11, 0..=11),
+            Compression::Zstd => (19, 1..=22),
+            Compression::Xz => (9, 0..=9),
+        };
+
+        match level {
+            None => Ok(default),
+            Some(level) if range.contains(&level) => Ok(level),
+            Some(level) => Err(Error::InvalidCompressionLevel {
+                compression: self,
+                level,
+            }),
+        }
+    }
+
+    pub(crate) fn decoder<'a>(self, r: impl Read + 'a) -> Result<Box<dyn Read + 'a>> {
+        let r = Source(r);
+
+        match self {
+            Compression::Raw => Ok(Box::new(r)),
+            #[cfg(feature = "bzip2")]
+            Compression::Bzip2 => Ok(Box::new(bzip2::read::BzDecoder::new(r))),
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => Ok(Box::new(brotli::Decompressor::new(r, 4096))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(Box::new(zstd::stream::read::Decoder::new(r)?)),
+            #[cfg(feature = "xz")]
+            Compression::Xz => Ok(Box::new(xz2::read::XzDecoder::new(r))),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(Box::new(lz4_flex::frame::FrameDecoder::new(r))),
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::UnsupportedCompression { name: self.name() }),
+        }
+    }
+
+    pub(crate) fn encode(self, data: &[u8], level: Option<u32>) -> Result<Vec<u8>> {
+        // Unused when none of the compressing backends is enabled.
+        #[allow(unused_variables)]
+        let level = self.level(level)?;
+
+        match self {
+            Compression::Raw => Ok(data.to_vec()),
+            #[cfg(feature = "bzip2")]
+            Compression::Bzip2 => {
+                use std::io::Write;
+
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::new(level));
+                encoder.write_all(data)?;
+
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "brotli")]
+            Compression::Brotli => {
+                use std::io::Write;
+
+                let mut encoded = Vec::new();
+                let mut encoder = brotli::CompressorWriter::new(&mut encoded, 4096, level, 22);
+                encoder.write_all(data)?;
+                drop(encoder);
+
+                Ok(encoded)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(zstd::stream::encode_all(data, level as i32)?),
+            #[cfg(feature = "xz")]
+            Compression::Xz => {
+                use std::io::Write;
+
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), level);
+                encoder.write_all(data)?;
+
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                use std::io::Write;
+
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(data)?;
+
+                encoder
+                    .finish()
+                    .map_err(|err| Error::Io(io::Error::other(err)))
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::UnsupportedCompression { name: self.name() }),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Marks errors of the underlying reader so they can be told apart from
+/// errors the decoder reports about the compressed data itself.
+struct Source<R>(R);
+
+impl<R: Read> Read for Source<R> {
+    #[cfg(feature = "std")]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(|err| match err.kind() {
+            io::ErrorKind::Interrupted => err,
+            kind => io::Error::new(kind, SourceError(err)),
+        })
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct SourceError(io::Error);
+
+#[cfg(feature = "std")]
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Splits an error returned by a decoder into `Ok` with the underlying
+/// reader's error, or `Err` with an error about the compressed data.
+#[cfg(feature = "std")]
+pub(crate) fn source_error(err: io::Error) -> core::result::Result<io::Error, io::Error> {
+    if !err.get_ref().is_some_and(|inner| inner.is::<SourceError>()) {
+        return Err(err);
+    }
+
+    let source = err.into_inner().unwrap().downcast::<SourceError>().unwrap();
+
+    Ok(source.0)
+}
+
+/// Without `std` only raw storage decodes, so every error is the reader's.
+#[cfg(not(feature = "std"))]
+pub(crate) fn source_error(err: io::Error) -> core::result::Result<io::Error, io::Error> {
+    Ok(err)
+}
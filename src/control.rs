@@ -0,0 +1,158 @@
+use alloc::vec::Vec;
+
+use crate::{Bsdiff4, Offsets, Result};
+
+/// One entry of the control block.
+///
+/// Applying it adds `diff_amount` diff bytes to as many bytes of the original,
+/// appends `extra_amount` bytes of the extra block, and then moves the
+/// position in the original by `seek`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Control {
+    pub(crate) diff_amount: u64,
+    pub(crate) extra_amount: u64,
+    pub(crate) seek: i64,
+}
+
+impl Control {
+    pub const fn new(diff_amount: u64, extra_amount: u64, seek: i64) -> Self {
+        Control {
+            diff_amount,
+            extra_amount,
+            seek,
+        }
+    }
+
+    pub const fn diff_amount(&self) -> u64 {
+        self.diff_amount
+    }
+
+    pub const fn extra_amount(&self) -> u64 {
+        self.extra_amount
+    }
+
+    pub const fn seek(&self) -> i64 {
+        self.seek
+    }
+}
+
+/// A control entry together with where it applies, yielded by [`Bsdiff4::controls`].
+#[derive(Debug, Clone, Copy)]
+pub struct ControlEntry<'a> {
+    control: Control,
+    index: usize,
+    new_offset: u64,
+    original_offset: u64,
+    diff: &'a [u8],
+    extra: &'a [u8],
+}
+
+impl<'a> ControlEntry<'a> {
+    pub fn control(&self) -> Control {
+        self.control
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Offset in the new file of the first byte this entry writes.
+    pub fn new_offset(&self) -> u64 {
+        self.new_offset
+    }
+
+    /// Offset in the original of the first byte the diff bytes are added to.
+    pub fn original_offset(&self) -> u64 {
+        self.original_offset
+    }
+
+    pub fn diff(&self) -> &'a [u8] {
+        self.diff
+    }
+
+    pub fn extra(&self) -> &'a [u8] {
+        self.extra
+    }
+}
+
+/// Iterator over the control entries of a patch, see [`Bsdiff4::controls`].
+#[derive(Clone)]
+pub struct Controls<'a> {
+    patch: &'a Bsdiff4,
+    offsets: Offsets,
+}
+
+impl<'a> Iterator for Controls<'a> {
+    type Item = ControlEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let control = *self.patch.control.get(self.offsets.control)?;
+        let offsets = self.offsets;
+
+        // Patches are validated on construction, so the slices are in bounds.
+        let diff_end = offsets.diff + control.diff_amount as usize;
+        let extra_end = offsets.extra + control.extra_amount as usize;
+
+        self.offsets = Offsets {
+            control: offsets.control + 1,
+            original: offsets
+                .original
+                .wrapping_add(control.diff_amount)
+                .wrapping_add_signed(control.seek),
+            diff: diff_end,
+            extra: extra_end,
+            new: diff_end + extra_end,
+        };
+
+        Some(ControlEntry {
+            control,
+            index: offsets.control,
+            new_offset: offsets.new as u64,
+            original_offset: offsets.original,
+            diff: &self.patch.diff[offsets.diff..diff_end],
+            extra: &self.patch.extra[offsets.extra..extra_end],
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.patch.control.len() - self.offsets.control;
+
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Controls<'_> {}
+
+impl Bsdiff4 {
+    /// Builds a patch from its decoded parts, validating them as
+    /// [`Bsdiff4::read`] does.
+    pub fn from_parts(
+        new_size: usize,
+        control: Vec<Control>,
+        diff: Vec<u8>,
+        extra: Vec<u8>,
+    ) -> Result<Self> {
+        let patch = Bsdiff4 {
+            new_size,
+            control,
+            diff,
+            extra,
+        };
+
+        patch.validate()?;
+
+        Ok(patch)
+    }
+
+    /// Returns the new size, control entries, diff block and extra block.
+    pub fn into_parts(self) -> (usize, Vec<Control>, Vec<u8>, Vec<u8>) {
+        (self.new_size, self.control, self.diff, self.extra)
+    }
+
+    pub fn controls(&self) -> Controls<'_> {
+        Controls {
+            patch: self,
+            offsets: Offsets::default(),
+        }
+    }
+}
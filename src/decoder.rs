@@ -0,0 +1,770 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::endsley::ENDSLEY_MAGIC;
+use crate::{read_control, read_header, Block, Compression, Control, Error, Header, Result};
+use crate::{CONTROL_SIZE, MAGIC};
+
+const STEP_SIZE: usize = 4 * 1024;
+
+/// A push-based patch decoder that performs no I/O of its own.
+///
+/// Patch bytes are handed to [`push`](Self::push) as they arrive, and
+/// [`poll`](Self::poll) reports what the decoder needs or produced next. The
+/// output is produced in order, in chunks of at most a few KiB.
+///
+/// The `ENDSLEY/BSDIFF43` layout is decoded without buffering any block. The
+/// block layouts store the control and diff blocks ahead of the extra block, so
+/// their compressed bytes are kept until the extra block starts to arrive.
+/// Raw storage and bzip2 are the supported block compressions.
+pub struct PatchDecoder {
+    input: Vec<u8>,
+    phase: Phase,
+    new_size: u64,
+    control: usize,
+    original_offset: u64,
+    new_offset: u64,
+    original: Vec<u8>,
+    output: Vec<u8>,
+}
+
+/// What a [`PatchDecoder`] needs or produced next.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// More patch bytes must be [pushed](PatchDecoder::push). Running out of
+    /// patch bytes at this point means the patch is truncated.
+    NeedInput,
+    /// `len` bytes of the original starting at `offset` must be
+    /// [supplied](PatchDecoder::supply_original).
+    NeedOriginal { offset: u64, len: usize },
+    /// A control entry is about to be applied.
+    Control(Control),
+    /// The next bytes of the new file.
+    Output(&'a [u8]),
+    /// The whole new file has been produced.
+    Done,
+}
+
+enum Phase {
+    Header,
+    Buffering {
+        compression: [Compression; 3],
+        len_control: u64,
+        len_diff: u64,
+        control: Vec<u8>,
+        diff: Vec<u8>,
+    },
+    Applying {
+        sources: Box<Sources>,
+        step: Step,
+    },
+    Done,
+}
+
+enum Sources {
+    Blocks {
+        control: Buffered,
+        diff: Buffered,
+        extra: Stream,
+    },
+    Interleaved(Stream),
+}
+
+enum Step {
+    NextControl,
+    Diff { control: Control, remaining: u64 },
+    Extra { control: Control, remaining: u64 },
+}
+
+enum Next {
+    NeedInput,
+    NeedOriginal { offset: u64, len: usize },
+    Control(Control),
+    Output,
+    Done,
+}
+
+impl PatchDecoder {
+    pub fn new() -> Self {
+        PatchDecoder {
+            input: Vec::new(),
+            phase: Phase::Header,
+            new_size: 0,
+            control: 0,
+            original_offset: 0,
+            new_offset: 0,
+            original: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// The size of the new file, once the header has been decoded.
+    pub fn new_size(&self) -> Option<u64> {
+        match self.phase {
+            Phase::Header => None,
+            _ => Some(self.new_size),
+        }
+    }
+
+    /// Queues the next bytes of the patch.
+    pub fn push(&mut self, patch: &[u8]) {
+        self.input.extend_from_slice(patch);
+    }
+
+    /// Answers an [`Event::NeedOriginal`] request.
+    ///
+    /// Fewer bytes than requested mean the original ended early.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no original bytes were requested.
+    pub fn supply_original(&mut self, original: &[u8]) -> Result<()> {
+        let len = match self.phase {
+            Phase::Applying {
+                step: Step::Diff { remaining, .. },
+                ..
+            } if self.original.is_empty() => remaining.min(STEP_SIZE as u64) as usize,
+            _ => panic!("no original bytes were requested"),
+        };
+
+        if original.len() < len {
+            return Err(Error::OriginalTooShort {
+                control: self.control,
+                original_offset: self.original_offset,
+            });
+        }
+
+        self.original.extend_from_slice(&original[..len]);
+
+        Ok(())
+    }
+
+    pub fn poll(&mut self) -> Result<Event<'_>> {
+        let event = match self.advance()? {
+            Next::NeedInput => Event::NeedInput,
+            Next::NeedOriginal { offset, len } => Event::NeedOriginal { offset, len },
+            Next::Control(control) => Event::Control(control),
+            Next::Output => Event::Output(&self.output),
+            Next::Done => Event::Done,
+        };
+
+        Ok(event)
+    }
+
+    fn advance(&mut self) -> Result<Next> {
+        self.output.clear();
+
+        loop {
+            match &mut self.phase {
+                Phase::Header => {
+                    let len = match self.input.get(..MAGIC.len()) {
+                        Some(magic) if ENDSLEY_MAGIC.starts_with(magic) => {
+                            ENDSLEY_MAGIC.len() + size_of::<u64>()
+                        }
+                        Some(_) => MAGIC.len() + 3 * size_of::<u64>(),
+                        None => return Ok(Next::NeedInput),
+                    };
+
+                    if self.input.len() < len {
+                        return Ok(Next::NeedInput);
+                    }
+
+                    let header = read_header(&mut &self.input[..len])?;
+                    self.input.drain(..len);
+                    self.new_size = header.new_size();
+
+                    self.phase = match header {
+                        Header::Blocks {
+                            compression,
+                            len_control,
+                            len_diff,
+                            ..
+                        } => Phase::Buffering {
+                            compression,
+                            len_control,
+                            len_diff,
+                            control: Vec::new(),
+                            diff: Vec::new(),
+                        },
+                        Header::Endsley { .. } => Phase::Applying {
+                            sources: Box::new(Sources::Interleaved(Stream::new(
+                                Compression::Bzip2,
+                                Block::Interleaved,
+                            )?)),
+                            step: Step::NextControl,
+                        },
+                    };
+                }
+                Phase::Buffering {
+                    compression: [control_compression, diff_compression, extra_compression],
+                    len_control,
+                    len_diff,
+                    control,
+                    diff,
+                } => {
+                    for (block, len) in [(&mut *control, *len_control), (&mut *diff, *len_diff)] {
+                        let missing = len - block.len() as u64;
+                        let n = missing.min(self.input.len() as u64) as usize;
+
+                        block.extend(self.input.drain(..n));
+                    }
+
+                    if control.len() as u64 != *len_control || diff.len() as u64 != *len_diff {
+                        return Ok(Next::NeedInput);
+                    }
+
+                    self.phase = Phase::Applying {
+                        sources: Box::new(Sources::Blocks {
+                            control: Buffered::new(
+                                Stream::new(*control_compression, Block::Control)?,
+                                core::mem::take(control),
+                            ),
+                            diff: Buffered::new(
+                                Stream::new(*diff_compression, Block::Diff)?,
+                                core::mem::take(diff),
+                            ),
+                            extra: Stream::new(*extra_compression, Block::Extra)?,
+                        }),
+                        step: Step::NextControl,
+                    };
+                }
+                Phase::Applying { sources, step } => {
+                    let next = apply_step(
+                        sources,
+                        step,
+                        &mut self.input,
+                        &mut self.original,
+                        &mut self.output,
+                        &mut Position {
+                            new_size: self.new_size,
+                            control: &mut self.control,
+                            original_offset: &mut self.original_offset,
+                            new_offset: &mut self.new_offset,
+                        },
+                    )?;
+
+                    if let Next::Done = next {
+                        self.phase = Phase::Done;
+                    }
+
+                    return Ok(next);
+                }
+                Phase::Done => return Ok(Next::Done),
+            }
+        }
+    }
+}
+
+impl Default for PatchDecoder {
+    fn default() -> Self {
+        PatchDecoder::new()
+    }
+}
+
+struct Position<'a> {
+    new_size: u64,
+    control: &'a mut usize,
+    original_offset: &'a mut u64,
+    new_offset: &'a mut u64,
+}
+
+impl Position<'_> {
+    fn block_too_short(&self, block: Block) -> Error {
+        Error::BlockTooShort {
+            block,
+            control: *self.control,
+            new_offset: *self.new_offset,
+        }
+    }
+}
+
+fn apply_step(
+    sources: &mut Sources,
+    step: &mut Step,
+    input: &mut Vec<u8>,
+    original: &mut Vec<u8>,
+    output: &mut Vec<u8>,
+    pos: &mut Position<'_>,
+) -> Result<Next> {
+    match step {
+        Step::NextControl => {
+            let entry = match sources {
+                Sources::Blocks { control, .. } => {
+                    let staged = control.fill(CONTROL_SIZE)?;
+
+                    if staged == 0 && control.is_exhausted() {
+                        return finish(sources, input, pos);
+                    }
+
+                    if staged < CONTROL_SIZE {
+                        return Err(Error::InvalidControlBlockSize {
+                            len: (*pos.control * CONTROL_SIZE + staged) as u64,
+                        });
+                    }
+
+                    control.stream.take(CONTROL_SIZE)
+                }
+                Sources::Interleaved(stream) => {
+                    if *pos.new_offset >= pos.new_size {
+                        return finish(sources, input, pos);
+                    }
+
+                    if stream.fill_from(input, CONTROL_SIZE)? < CONTROL_SIZE {
+                        return match stream.done {
+                            true => Err(pos.block_too_short(Block::Control)),
+                            false => Ok(Next::NeedInput),
+                        };
+                    }
+
+                    stream.take(CONTROL_SIZE)
+                }
+            };
+
+            let control = read_control(&mut &entry[..], *pos.control)?;
+
+            *step = Step::Diff {
+                control,
+                remaining: control.diff_amount,
+            };
+
+            Ok(Next::Control(control))
+        }
+        Step::Diff { control, remaining } => {
+            if *remaining == 0 {
+                *step = Step::Extra {
+                    control: *control,
+                    remaining: control.extra_amount,
+                };
+
+                return apply_step(sources, step, input, original, output, pos);
+            }
+
+            let len = (*remaining).min(STEP_SIZE as u64) as usize;
+
+            if original.is_empty() {
+                return Ok(Next::NeedOriginal {
+                    offset: *pos.original_offset,
+                    len,
+                });
+            }
+
+            let diff = match sources {
+                Sources::Blocks { diff, .. } => {
+                    if diff.fill(len)? < len {
+                        return Err(pos.block_too_short(Block::Diff));
+                    }
+
+                    &mut diff.stream
+                }
+                Sources::Interleaved(stream) => {
+                    if stream.fill_from(input, len)? < len {
+                        return match stream.done {
+                            true => Err(pos.block_too_short(Block::Diff)),
+                            false => Ok(Next::NeedInput),
+                        };
+                    }
+
+                    stream
+                }
+            };
+
+            output.extend(
+                original
+                    .drain(..)
+                    .zip(diff.take(len))
+                    .map(|(orig, diff)| orig.wrapping_add(*diff)),
+            );
+
+            *remaining -= len as u64;
+            *pos.original_offset += len as u64;
+            *pos.new_offset += len as u64;
+
+            Ok(Next::Output)
+        }
+        Step::Extra { control, remaining } => {
+            if *remaining == 0 {
+                *pos.original_offset = pos.original_offset.checked_add_signed(control.seek).ok_or(
+                    Error::InvalidSeek {
+                        control: *pos.control,
+                        original_offset: *pos.original_offset,
+                        seek: control.seek,
+                    },
+                )?;
+                *pos.control += 1;
+                *step = Step::NextControl;
+
+                return apply_step(sources, step, input, original, output, pos);
+            }
+
+            let want = (*remaining).min(STEP_SIZE as u64) as usize;
+            let stream = match sources {
+                Sources::Blocks { extra, .. } => extra,
+                Sources::Interleaved(stream) => stream,
+            };
+            let len = stream.fill_from(input, want)?;
+
+            if len == 0 {
+                return match stream.done {
+                    true => Err(pos.block_too_short(Block::Extra)),
+                    false => Ok(Next::NeedInput),
+                };
+            }
+
+            output.extend_from_slice(stream.take(len));
+
+            *remaining -= len as u64;
+            *pos.new_offset += len as u64;
+
+            Ok(Next::Output)
+        }
+    }
+}
+
+/// Checks the output size and that every block ends where the controls stop
+/// using it, waiting for more input until a streamed block reaches its end.
+fn finish(sources: &mut Sources, input: &mut Vec<u8>, pos: &Position<'_>) -> Result<Next> {
+    if *pos.new_offset != pos.new_size {
+        return Err(Error::NewSizeMismatch {
+            expected: pos.new_size,
+            actual: *pos.new_offset,
+        });
+    }
+
+    let stream = match sources {
+        Sources::Blocks {
+            control,
+            diff,
+            extra,
+        } => {
+            for buffered in [control, diff] {
+                let staged = buffered.fill(1)?;
+                buffered.stream.check_unused(staged)?;
+
+                if !buffered.stream.has_ended() {
+                    return Err(Error::TruncatedBlock {
+                        block: buffered.stream.block,
+                    });
+                }
+            }
+
+            extra
+        }
+        Sources::Interleaved(stream) => stream,
+    };
+
+    let staged = stream.fill_from(input, 1)?;
+    stream.check_unused(staged)?;
+
+    if !stream.has_ended() {
+        return Ok(Next::NeedInput);
+    }
+
+    Ok(Next::Done)
+}
+
+/// Decompresses one block, staging decoded bytes until they are taken.
+struct Stream {
+    inflate: Inflate,
+    block: Block,
+    decoded: Vec<u8>,
+    pos: usize,
+    taken: u64,
+    done: bool,
+}
+
+impl Stream {
+    fn new(compression: Compression, block: Block) -> Result<Self> {
+        Ok(Stream {
+            inflate: Inflate::new(compression)?,
+            block,
+            decoded: Vec::new(),
+            pos: 0,
+            taken: 0,
+            done: false,
+        })
+    }
+
+    /// Decodes from the front of `input` until `want` bytes are staged or the
+    /// input runs out, and returns the number of bytes staged.
+    fn fill_from(&mut self, input: &mut Vec<u8>, want: usize) -> Result<usize> {
+        let consumed = self.fill(input, want)?;
+        input.drain(..consumed);
+
+        Ok(self.decoded.len() - self.pos)
+    }
+
+    fn fill(&mut self, input: &[u8], want: usize) -> Result<usize> {
+        self.decoded.drain(..self.pos);
+        self.pos = 0;
+
+        let mut consumed = 0;
+
+        while self.decoded.len() < want && !self.done {
+            let start = self.decoded.len();
+            self.decoded.resize(want, 0);
+
+            let (read, written, done) =
+                self.inflate
+                    .decode(&input[consumed..], &mut self.decoded[start..], self.block)?;
+
+            self.decoded.truncate(start + written);
+            self.done = done;
+            consumed += read;
+
+            if read == 0 && written == 0 {
+                break;
+            }
+        }
+
+        Ok(consumed)
+    }
+
+    fn take(&mut self, len: usize) -> &[u8] {
+        let start = self.pos;
+        self.pos += len;
+        self.taken += len as u64;
+
+        &self.decoded[start..self.pos]
+    }
+
+    /// Fails if `staged` decoded bytes are left over once the controls are done.
+    fn check_unused(&self, staged: usize) -> Result<()> {
+        if staged > 0 {
+            return Err(Error::BlockSizeMismatch {
+                block: self.block,
+                expected: self.taken,
+                actual: self.taken + staged as u64,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Whether the end of the block has been seen, which raw blocks lack.
+    fn has_ended(&self) -> bool {
+        self.done || !self.inflate.is_framed()
+    }
+}
+
+/// A stream whose compressed bytes are all held in memory.
+struct Buffered {
+    stream: Stream,
+    input: Vec<u8>,
+    pos: usize,
+}
+
+impl Buffered {
+    fn new(stream: Stream, input: Vec<u8>) -> Self {
+        Buffered {
+            stream,
+            input,
+            pos: 0,
+        }
+    }
+
+    fn fill(&mut self, want: usize) -> Result<usize> {
+        self.pos += self.stream.fill(&self.input[self.pos..], want)?;
+
+        Ok(self.stream.decoded.len() - self.stream.pos)
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.stream.done || self.pos == self.input.len()
+    }
+}
+
+pub(crate) enum Inflate {
+    Raw,
+    #[cfg(feature = "bzip2")]
+    Bzip2(Box<bzip2::Decompress>),
+}
+
+impl Inflate {
+    pub(crate) fn new(compression: Compression) -> Result<Self> {
+        match compression {
+            Compression::Raw => Ok(Inflate::Raw),
+            #[cfg(feature = "bzip2")]
+            Compression::Bzip2 => Ok(Inflate::Bzip2(Box::new(bzip2::Decompress::new(false)))),
+            _ => Err(Error::UnsupportedCompression {
+                name: compression.name(),
+            }),
+        }
+    }
+
+    /// Whether the codec marks the end of its stream.
+    pub(crate) fn is_framed(&self) -> bool {
+        !matches!(self, Inflate::Raw)
+    }
+
+    /// Returns the bytes read and written, and whether the block has ended.
+    #[cfg_attr(not(feature = "bzip2"), allow(unused_variables))]
+    pub(crate) fn decode(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        block: Block,
+    ) -> Result<(usize, usize, bool)> {
+        match self {
+            Inflate::Raw => {
+                let len = input.len().min(output.len());
+                output[..len].copy_from_slice(&input[..len]);
+
+                Ok((len, len, false))
+            }
+            #[cfg(feature = "bzip2")]
+            Inflate::Bzip2(decompress) => {
+                let total_in = decompress.total_in();
+                let total_out = decompress.total_out();
+
+                let status =
+                    decompress
+                        .decompress(input, output)
+                        .map_err(|err| Error::CorruptBlock {
+                            block,
+                            source: std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+                        })?;
+
+                if let bzip2::Status::MemNeeded = status {
+                    return Err(Error::Io(std::io::ErrorKind::OutOfMemory.into()));
+                }
+
+                Ok((
+                    (decompress.total_in() - total_in) as usize,
+                    (decompress.total_out() - total_out) as usize,
+                    matches!(status, bzip2::Status::StreamEnd),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{Event, PatchDecoder};
+    use crate::diff::tests::{edit, sample};
+    use crate::{Block, Bsdiff4, Compression, Error, Result};
+
+    /// Feeds `patch` to a decoder `step` bytes at a time and collects the
+    /// output, or returns `None` if the decoder wants more than `patch`.
+    fn decode(patch: &[u8], original: &[u8], step: usize) -> Result<Option<Vec<u8>>> {
+        let mut decoder = PatchDecoder::new();
+        let mut input = patch.chunks(step);
+        let mut new = Vec::new();
+
+        loop {
+            match decoder.poll()? {
+                Event::NeedInput => match input.next() {
+                    Some(chunk) => decoder.push(chunk),
+                    None => return Ok(None),
+                },
+                Event::NeedOriginal { offset, len } => {
+                    let offset = offset as usize;
+                    decoder.supply_original(&original[offset..offset + len])?;
+                }
+                Event::Control(_) => {}
+                Event::Output(bytes) => new.extend_from_slice(bytes),
+                Event::Done => return Ok(Some(new)),
+            }
+        }
+    }
+
+    fn patches() -> (Vec<u8>, Vec<u8>, Vec<Vec<u8>>) {
+        let old = sample(20_000, 3);
+        let patch = Bsdiff4::diff(&old, &edit(&old, 4));
+        let new = patch.apply_to_slice(&old).unwrap();
+
+        let mut encoded = Vec::new();
+        patch
+            .write_compressed(&mut encoded, Compression::Raw, None)
+            .unwrap();
+        #[cfg_attr(not(feature = "bzip2"), allow(unused_mut))]
+        let mut patches = vec![encoded];
+
+        #[cfg(feature = "bzip2")]
+        {
+            let mut bsdiff40 = Vec::new();
+            patch.write(&mut bsdiff40).unwrap();
+            let mut endsley = Vec::new();
+            patch.write_endsley(&mut endsley).unwrap();
+
+            patches.extend([bsdiff40, endsley]);
+        }
+
+        (old, new, patches)
+    }
+
+    #[test]
+    fn decode_in_small_pieces() {
+        let (old, new, patches) = patches();
+
+        for encoded in &patches {
+            for step in [1, 7, 4096, encoded.len()] {
+                assert_eq!(decode(encoded, &old, step).unwrap(), Some(new.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let (old, _, patches) = patches();
+
+        for encoded in &patches {
+            for cut in [1, 3, 8] {
+                for step in [7, encoded.len()] {
+                    assert!(matches!(
+                        decode(&encoded[..encoded.len() - cut], &old, step),
+                        Ok(None) | Err(Error::TruncatedBlock { .. })
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decode_trailing_bytes() {
+        let (old, _, patches) = patches();
+        let mut raw = patches[0].clone();
+        raw.push(0);
+
+        assert!(matches!(
+            decode(&raw, &old, raw.len()),
+            Err(Error::BlockSizeMismatch {
+                block: Block::Extra,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn decode_corrupted() {
+        let (old, _, patches) = patches();
+        let bsdiff40 = &patches[1];
+
+        let len_control = u64::from_le_bytes(bsdiff40[8..16].try_into().unwrap()) as usize;
+        let len_diff = u64::from_le_bytes(bsdiff40[16..24].try_into().unwrap()) as usize;
+        let extra = 32 + len_control + len_diff;
+
+        let mut corrupted = bsdiff40.clone();
+        corrupted[extra + (bsdiff40.len() - extra) / 2] ^= 0x04;
+
+        // Bytes decoded before the bad block checksum may already be more than
+        // the controls use.
+        for step in [7, corrupted.len()] {
+            assert!(matches!(
+                decode(&corrupted, &old, step),
+                Err(Error::CorruptBlock {
+                    block: Block::Extra,
+                    ..
+                } | Error::BlockSizeMismatch {
+                    block: Block::Extra,
+                    ..
+                })
+            ));
+        }
+    }
+}
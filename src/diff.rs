@@ -0,0 +1,446 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::mem::size_of;
+
+use crate::{Bsdiff4, Control};
+
+impl Bsdiff4 {
+    /// Rough peak memory used by [`Bsdiff4::diff`], including both inputs.
+    pub fn diff_memory_estimate(old_len: usize, new_len: usize) -> u64 {
+        let old_len = old_len as u64;
+        let new_len = new_len as u64;
+        let suffix_array = 2 * (old_len + 1) * size_of::<isize>() as u64;
+
+        old_len + suffix_array + 3 * new_len
+    }
+
+    pub fn diff(old: &[u8], new: &[u8]) -> Self {
+        let suffixes = qsufsort(old);
+
+        let mut control = Vec::new();
+        let mut diff = Vec::with_capacity(new.len());
+        let mut extra = Vec::new();
+
+        let old_size = old.len() as isize;
+        let new_size = new.len() as isize;
+
+        let mut scan = 0;
+        let mut len = 0;
+        let mut pos = 0;
+        let mut last_scan = 0;
+        let mut last_pos = 0;
+        let mut last_offset = 0;
+
+        while scan < new_size {
+            let mut old_score = 0;
+
+            scan += len;
+            let mut scsc = scan;
+
+            while scan < new_size {
+                (pos, len) = search(&suffixes, old, &new[scan as usize..], 0, old_size);
+
+                while scsc < scan + len {
+                    if scsc + last_offset < old_size
+                        && old[(scsc + last_offset) as usize] == new[scsc as usize]
+                    {
+                        old_score += 1;
+                    }
+
+                    scsc += 1;
+                }
+
+                if (len == old_score && len != 0) || len > old_score + 8 {
+                    break;
+                }
+
+                if scan + last_offset < old_size
+                    && old[(scan + last_offset) as usize] == new[scan as usize]
+                {
+                    old_score -= 1;
+                }
+
+                scan += 1;
+            }
+
+            if len == old_score && scan != new_size {
+                continue;
+            }
+
+            let mut len_forward = 0;
+            {
+                let mut score = 0;
+                let mut best_score = 0;
+                let mut i = 0;
+
+                while last_scan + i < scan && last_pos + i < old_size {
+                    if old[(last_pos + i) as usize] == new[(last_scan + i) as usize] {
+                        score += 1;
+                    }
+
+                    i += 1;
+
+                    if score * 2 - i > best_score * 2 - len_forward {
+                        best_score = score;
+                        len_forward = i;
+                    }
+                }
+            }
+
+            let mut len_backward = 0;
+            if scan < new_size {
+                let mut score = 0;
+                let mut best_score = 0;
+                let mut i = 1;
+
+                while scan >= last_scan + i && pos >= i {
+                    if old[(pos - i) as usize] == new[(scan - i) as usize] {
+                        score += 1;
+                    }
+
+                    if score * 2 - i > best_score * 2 - len_backward {
+                        best_score = score;
+                        len_backward = i;
+                    }
+
+                    i += 1;
+                }
+            }
+
+            if last_scan + len_forward > scan - len_backward {
+                let overlap = (last_scan + len_forward) - (scan - len_backward);
+                let mut score = 0;
+                let mut best_score = 0;
+                let mut len_split = 0;
+
+                for i in 0..overlap {
+                    if new[(last_scan + len_forward - overlap + i) as usize]
+                        == old[(last_pos + len_forward - overlap + i) as usize]
+                    {
+                        score += 1;
+                    }
+
+                    if new[(scan - len_backward + i) as usize]
+                        == old[(pos - len_backward + i) as usize]
+                    {
+                        score -= 1;
+                    }
+
+                    if score > best_score {
+                        best_score = score;
+                        len_split = i + 1;
+                    }
+                }
+
+                len_forward += len_split - overlap;
+                len_backward -= len_split;
+            }
+
+            let diff_start = last_scan as usize;
+            let diff_end = (last_scan + len_forward) as usize;
+            let old_start = last_pos as usize;
+
+            diff.extend(
+                new[diff_start..diff_end]
+                    .iter()
+                    .zip(&old[old_start..])
+                    .map(|(new, old)| new.wrapping_sub(*old)),
+            );
+
+            let extra_end = (scan - len_backward) as usize;
+
+            extra.extend_from_slice(&new[diff_end..extra_end]);
+
+            control.push(Control {
+                diff_amount: len_forward as u64,
+                extra_amount: (extra_end - diff_end) as u64,
+                seek: ((pos - len_backward) - (last_pos + len_forward)) as i64,
+            });
+
+            last_scan = scan - len_backward;
+            last_pos = pos - len_backward;
+            last_offset = pos - scan;
+        }
+
+        Bsdiff4 {
+            new_size: new.len(),
+            control,
+            diff,
+            extra,
+        }
+    }
+}
+
+fn match_len(old: &[u8], new: &[u8]) -> isize {
+    old.iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count() as isize
+}
+
+fn search(suffixes: &[isize], old: &[u8], new: &[u8], start: isize, end: isize) -> (isize, isize) {
+    if end - start < 2 {
+        let start_pos = suffixes[start as usize];
+        let end_pos = suffixes[end as usize];
+        let start_len = match_len(&old[start_pos as usize..], new);
+        let end_len = match_len(&old[end_pos as usize..], new);
+
+        return if start_len > end_len {
+            (start_pos, start_len)
+        } else {
+            (end_pos, end_len)
+        };
+    }
+
+    let mid = start + (end - start) / 2;
+    let suffix = &old[suffixes[mid as usize] as usize..];
+    let len = suffix.len().min(new.len());
+
+    match suffix[..len].cmp(&new[..len]) {
+        Ordering::Less => search(suffixes, old, new, mid, end),
+        _ => search(suffixes, old, new, start, mid),
+    }
+}
+
+fn qsufsort(old: &[u8]) -> Vec<isize> {
+    let old_size = old.len() as isize;
+    let mut suffixes = vec![0; old.len() + 1];
+    let mut ranks = vec![0; old.len() + 1];
+    let mut buckets = [0isize; 256];
+
+    for &byte in old {
+        buckets[byte as usize] += 1;
+    }
+
+    for i in 1..256 {
+        buckets[i] += buckets[i - 1];
+    }
+
+    for i in (1..256).rev() {
+        buckets[i] = buckets[i - 1];
+    }
+
+    buckets[0] = 0;
+
+    for (i, &byte) in old.iter().enumerate() {
+        buckets[byte as usize] += 1;
+        suffixes[buckets[byte as usize] as usize] = i as isize;
+    }
+
+    suffixes[0] = old_size;
+
+    for (i, &byte) in old.iter().enumerate() {
+        ranks[i] = buckets[byte as usize];
+    }
+
+    ranks[old.len()] = 0;
+
+    for i in 1..256 {
+        if buckets[i] == buckets[i - 1] + 1 {
+            suffixes[buckets[i] as usize] = -1;
+        }
+    }
+
+    suffixes[0] = -1;
+
+    let mut h = 1;
+
+    while suffixes[0] != -(old_size + 1) {
+        let mut len = 0;
+        let mut i = 0;
+
+        while i < old_size + 1 {
+            if suffixes[i as usize] < 0 {
+                len -= suffixes[i as usize];
+                i -= suffixes[i as usize];
+            } else {
+                if len != 0 {
+                    suffixes[(i - len) as usize] = -len;
+                }
+
+                len = ranks[suffixes[i as usize] as usize] + 1 - i;
+                split(&mut suffixes, &mut ranks, i, len, h);
+                i += len;
+                len = 0;
+            }
+        }
+
+        if len != 0 {
+            suffixes[(i - len) as usize] = -len;
+        }
+
+        h += h;
+    }
+
+    for (i, &rank) in ranks.iter().enumerate() {
+        suffixes[rank as usize] = i as isize;
+    }
+
+    suffixes
+}
+
+fn split(suffixes: &mut [isize], ranks: &mut [isize], start: isize, len: isize, h: isize) {
+    let key =
+        |suffixes: &[isize], ranks: &[isize], i: isize| ranks[(suffixes[i as usize] + h) as usize];
+
+    if len < 16 {
+        let mut k = start;
+
+        while k < start + len {
+            let mut j = 1;
+            let mut x = key(suffixes, ranks, k);
+            let mut i = 1;
+
+            while k + i < start + len {
+                let rank = key(suffixes, ranks, k + i);
+
+                if rank < x {
+                    x = rank;
+                    j = 0;
+                }
+
+                if rank == x {
+                    suffixes.swap((k + j) as usize, (k + i) as usize);
+                    j += 1;
+                }
+
+                i += 1;
+            }
+
+            for i in 0..j {
+                ranks[suffixes[(k + i) as usize] as usize] = k + j - 1;
+            }
+
+            if j == 1 {
+                suffixes[k as usize] = -1;
+            }
+
+            k += j;
+        }
+
+        return;
+    }
+
+    let x = key(suffixes, ranks, start + len / 2);
+    let mut jj = 0;
+    let mut kk = 0;
+
+    for i in start..start + len {
+        let rank = key(suffixes, ranks, i);
+
+        if rank < x {
+            jj += 1;
+        }
+
+        if rank == x {
+            kk += 1;
+        }
+    }
+
+    jj += start;
+    kk += jj;
+
+    let mut i = start;
+    let mut j = 0;
+    let mut k = 0;
+
+    while i < jj {
+        let rank = key(suffixes, ranks, i);
+
+        match rank.cmp(&x) {
+            Ordering::Less => i += 1,
+            Ordering::Equal => {
+                suffixes.swap(i as usize, (jj + j) as usize);
+                j += 1;
+            }
+            Ordering::Greater => {
+                suffixes.swap(i as usize, (kk + k) as usize);
+                k += 1;
+            }
+        }
+    }
+
+    while jj + j < kk {
+        if key(suffixes, ranks, jj + j) == x {
+            j += 1;
+        } else {
+            suffixes.swap((jj + j) as usize, (kk + k) as usize);
+            k += 1;
+        }
+    }
+
+    if jj > start {
+        split(suffixes, ranks, start, jj - start, h);
+    }
+
+    for i in 0..kk - jj {
+        ranks[suffixes[(jj + i) as usize] as usize] = kk - 1;
+    }
+
+    if jj == kk - 1 {
+        suffixes[jj as usize] = -1;
+    }
+
+    if start + len > kk {
+        split(suffixes, ranks, kk, start + len - kk, h);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use alloc::vec::Vec;
+
+    use crate::Bsdiff4;
+
+    /// Deterministic pseudo-random bytes.
+    pub(crate) fn sample(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed | 1;
+
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Returns `data` with a few bytes changed, a block moved to the front and
+    /// new bytes inserted, as a typical new version would.
+    pub(crate) fn edit(data: &[u8], seed: u64) -> Vec<u8> {
+        let third = data.len() / 3;
+        let mut edited = data[2 * third..].to_vec();
+
+        edited.extend_from_slice(&data[..third]);
+        edited.extend(sample(100, seed));
+        edited.extend_from_slice(&data[third..2 * third]);
+
+        for i in (0..edited.len()).step_by(97) {
+            edited[i] = edited[i].wrapping_add(1);
+        }
+
+        edited
+    }
+
+    #[test]
+    fn diff_round_trip() {
+        let old = sample(10_000, 1);
+        let new = edit(&old, 2);
+
+        for (old, new) in [
+            (&old[..], &new[..]),
+            (&new[..], &old[..]),
+            (&old[..], &old[..]),
+            (&[][..], &new[..]),
+            (&old[..], &[][..]),
+            (&[][..], &[][..]),
+        ] {
+            let patch = Bsdiff4::diff(old, new);
+
+            assert_eq!(patch.new_size(), new.len());
+            assert_eq!(patch.apply_to_slice(old).unwrap(), new);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use alloc::vec::Vec;
+
+use crate::io::{Read, Write};
+use crate::{
+    read_block_to_end, read_control, write_control, write_positive_le_i64, Block, Bsdiff4,
+    Compression, Error, Limits, Result, CONTROL_SIZE,
+};
+
+pub(crate) const ENDSLEY_MAGIC: &[u8] = b"ENDSLEY/BSDIFF43";
+
+impl Bsdiff4 {
+    pub fn write_endsley<W: Write>(&self, w: &mut W) -> Result<()> {
+        self.write_endsley_with_level(w, 9)
+    }
+
+    /// Writes the patch in the `ENDSLEY/BSDIFF43` layout, where control, diff
+    /// and extra data are interleaved in a single bzip2 stream.
+    pub fn write_endsley_with_level<W: Write>(&self, w: &mut W, level: u32) -> Result<()> {
+        let mut diff = self.diff.as_slice();
+        let mut extra = self.extra.as_slice();
+        let mut stream = Vec::with_capacity(self.control.len() * CONTROL_SIZE + self.new_size);
+
+        for control in &self.control {
+            write_control(&mut stream, control)?;
+
+            let (diff_chunk, rest) = diff.split_at(control.diff_amount as usize);
+            stream.write_all(diff_chunk)?;
+            diff = rest;
+
+            let (extra_chunk, rest) = extra.split_at(control.extra_amount as usize);
+            stream.write_all(extra_chunk)?;
+            extra = rest;
+        }
+
+        let stream = Compression::Bzip2.encode(&stream, Some(level))?;
+
+        w.write_all(ENDSLEY_MAGIC)?;
+        write_positive_le_i64(w, self.new_size as u64, "new_size")?;
+        w.write_all(&stream)?;
+
+        Ok(())
+    }
+}
+
+pub(crate) fn read_endsley_body(
+    r: &mut impl Read,
+    new_size: usize,
+    limits: &Limits,
+) -> Result<Bsdiff4> {
+    let stream = read_block_to_end(r, Compression::Bzip2, Block::Interleaved, limits)?;
+
+    parse_endsley(&stream, new_size, limits)
+}
+
+/// Splits the decompressed interleaved stream into control, diff and extra.
+pub(crate) fn parse_endsley(
+    mut stream: &[u8],
+    new_size: usize,
+    limits: &Limits,
+) -> Result<Bsdiff4> {
+    let mut control = Vec::new();
+    let mut diff = Vec::new();
+    let mut extra = Vec::new();
+    let mut new_offset = 0u64;
+
+    while new_offset < new_size as u64 {
+        if stream.len() < CONTROL_SIZE {
+            return Err(Error::BlockTooShort {
+                block: Block::Control,
+                control: control.len(),
+                new_offset,
+            });
+        }
+
+        if control.len() as u64 >= limits.max_controls {
+            return Err(Error::TooManyControls {
+                count: control.len() as u64 + 1,
+                max: limits.max_controls,
+            });
+        }
+
+        let (entry, rest) = stream.split_at(CONTROL_SIZE);
+        let entry = read_control(&mut &entry[..], control.len())?;
+        stream = rest;
+
+        for (block, amount, data) in [
+            (Block::Diff, entry.diff_amount, &mut diff),
+            (Block::Extra, entry.extra_amount, &mut extra),
+        ] {
+            if amount > stream.len() as u64 {
+                return Err(Error::BlockTooShort {
+                    block,
+                    control: control.len(),
+                    new_offset,
+                });
+            }
+
+            let (chunk, rest) = stream.split_at(amount as usize);
+            data.extend_from_slice(chunk);
+            stream = rest;
+            new_offset += amount;
+        }
+
+        control.push(entry);
+    }
+
+    Ok(Bsdiff4 {
+        new_size,
+        control,
+        diff,
+        extra,
+    })
+}
@@ -0,0 +1,115 @@
+use core::fmt;
+
+use crate::compression::{source_error, Compression};
+use crate::io;
+
+#[cfg(feature = "checksum")]
+use crate::checksum::Checksum;
+
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid bsdiff4 magic")]
+    InvalidMagic,
+    #[error("truncated bsdiff4 header")]
+    TruncatedHeader,
+    #[error("negative value {value} for `{field}`")]
+    NegativeLength { field: &'static str, value: i64 },
+    #[error("value {value} for `{field}` is out of range")]
+    LengthOutOfRange { field: &'static str, value: u64 },
+    #[error("negative value {value} for `{field}` in control {control}")]
+    NegativeControlLength {
+        control: usize,
+        field: &'static str,
+        value: i64,
+    },
+    #[error("invalid control block size {len}")]
+    InvalidControlBlockSize { len: u64 },
+    #[error("declared output size {new_size} exceeds the limit of {max} bytes")]
+    NewSizeTooLarge { new_size: u64, max: u64 },
+    #[error("{count} control entries exceed the limit of {max}")]
+    TooManyControls { count: u64, max: u64 },
+    #[error("{block} block exceeds the limit of {max} decompressed bytes")]
+    BlockTooLarge { block: Block, max: u64 },
+    #[error("{block} block exceeds the maximum expansion ratio of {max}")]
+    ExpansionRatioExceeded { block: Block, max: u64 },
+    #[error("unknown compression type {id}")]
+    UnknownCompression { id: u8 },
+    #[error("{name} compression is not enabled")]
+    UnsupportedCompression { name: &'static str },
+    #[error("truncated compressed stream in {block} block")]
+    TruncatedBlock { block: Block },
+    #[error("corrupt compressed stream in {block} block")]
+    CorruptBlock {
+        block: Block,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{block} block exhausted at control {control} (new offset {new_offset})")]
+    BlockTooShort {
+        block: Block,
+        control: usize,
+        new_offset: u64,
+    },
+    #[error("{block} block is {actual} bytes but the controls use {expected}")]
+    BlockSizeMismatch {
+        block: Block,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("header declares {expected} output bytes but the controls produce {actual}")]
+    NewSizeMismatch { expected: u64, actual: u64 },
+    #[error("original exhausted at control {control} (original offset {original_offset})")]
+    OriginalTooShort {
+        control: usize,
+        original_offset: u64,
+    },
+    #[error("seek by {seek} from original offset {original_offset} at control {control} is out of range")]
+    InvalidSeek {
+        control: usize,
+        original_offset: u64,
+        seek: i64,
+    },
+    #[error("invalid {compression} compression level {level}")]
+    InvalidCompressionLevel {
+        compression: Compression,
+        level: u32,
+    },
+    #[cfg(feature = "checksum")]
+    #[error("checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        expected: Checksum,
+        actual: Checksum,
+    },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Block {
+    Control,
+    Diff,
+    Extra,
+    /// The single stream of the `ENDSLEY/BSDIFF43` format.
+    Interleaved,
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Block::Control => "control",
+            Block::Diff => "diff",
+            Block::Extra => "extra",
+            Block::Interleaved => "interleaved",
+        })
+    }
+}
+
+pub(crate) fn decode_error(block: Block, err: io::Error) -> Error {
+    match source_error(err) {
+        Ok(source) => Error::Io(source),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Error::TruncatedBlock { block },
+        Err(err) => Error::CorruptBlock { block, source: err },
+    }
+}
@@ -0,0 +1,415 @@
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use itertools::izip;
+
+use crate::io::{self, Read, Seek, SeekFrom, Write};
+use crate::{Bsdiff4, Error, Result, CHUNK_SIZE};
+
+/// Files that can be truncated or extended, as needed by
+/// [`Bsdiff4::apply_in_place`].
+pub trait SetLen {
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+}
+
+#[cfg(feature = "std")]
+impl SetLen for std::fs::File {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        std::fs::File::set_len(self, len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl SetLen for &std::fs::File {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        std::fs::File::set_len(self, len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl SetLen for io::Cursor<Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        resize(self.get_mut(), len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl SetLen for io::Cursor<&mut Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        resize(self.get_mut(), len)
+    }
+}
+
+impl<T: SetLen + ?Sized> SetLen for &mut T {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        (**self).set_len(len)
+    }
+}
+
+#[cfg(feature = "std")]
+fn resize(vec: &mut Vec<u8>, len: u64) -> io::Result<()> {
+    let len = usize::try_from(len).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "length does not fit in memory")
+    })?;
+
+    vec.resize(len, 0);
+
+    Ok(())
+}
+
+/// Original bytes starting at `offset`, saved before they are overwritten.
+struct Buffered {
+    offset: u64,
+    bytes: Vec<u8>,
+}
+
+/// The diff bytes of one control entry, read from `read` in the original and
+/// written to `write` in the new file.
+struct Run<'a> {
+    read: u64,
+    write: u64,
+    diff: &'a [u8],
+}
+
+impl Run<'_> {
+    fn read_end(&self) -> u64 {
+        self.read + self.diff.len() as u64
+    }
+
+    fn write_end(&self) -> u64 {
+        self.write + self.diff.len() as u64
+    }
+}
+
+impl Bsdiff4 {
+    /// Applies the patch to `file` in place, replacing the original with the
+    /// new file, which is then truncated or extended to [`new_size`](Self::new_size).
+    ///
+    /// Diff regions are written in an order that reads every original byte
+    /// before it is overwritten. Where regions depend on each other in a cycle,
+    /// only the original bytes that would be overwritten are buffered in
+    /// memory. Offsets are from the start of `file`.
+    ///
+    /// The patch is checked against the file size before anything is written,
+    /// but an I/O error part way through leaves `file` neither original nor new.
+    pub fn apply_in_place(&self, file: &mut (impl Read + Write + Seek + SetLen)) -> Result<()> {
+        let original_len = file.seek(SeekFrom::End(0))?;
+
+        let runs = self
+            .controls()
+            .filter(|entry| !entry.diff().is_empty())
+            .map(|entry| {
+                let run = Run {
+                    read: entry.original_offset(),
+                    write: entry.new_offset(),
+                    diff: entry.diff(),
+                };
+
+                match run.read.checked_add(run.diff.len() as u64) {
+                    Some(end) if end <= original_len => Ok(run),
+                    _ => Err(Error::OriginalTooShort {
+                        control: entry.index(),
+                        original_offset: run.read,
+                    }),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (writers, mut blockers) = hazards(&runs);
+        let mut queue: VecDeque<usize> = (0..runs.len()).filter(|&i| blockers[i] == 0).collect();
+        let mut read = vec![false; runs.len()];
+        let mut buffered: Vec<Vec<Buffered>> = runs.iter().map(|_| Vec::new()).collect();
+        let mut smallest: Vec<usize> = (0..runs.len()).collect();
+        smallest.sort_by_key(|&i| runs[i].diff.len());
+        let mut smallest = smallest.into_iter();
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let mut written = 0;
+
+        while written < runs.len() {
+            let Some(i) = queue.pop_front() else {
+                // Every remaining run would overwrite bytes another still has
+                // to read, so buffer those bytes of the smallest one to break
+                // the cycle.
+                let i = smallest
+                    .find(|&i| !read[i])
+                    .expect("a cycle has an unread run");
+
+                buffered[i] = read_overwritten(file, &runs, i, &writers[i])?;
+                release(i, &writers, &mut blockers, &mut read, &mut queue);
+
+                continue;
+            };
+
+            copy(
+                file,
+                &runs[i],
+                &core::mem::take(&mut buffered[i]),
+                &mut chunk,
+            )?;
+
+            if !read[i] {
+                release(i, &writers, &mut blockers, &mut read, &mut queue);
+            }
+
+            written += 1;
+        }
+
+        // Extra bytes read nothing from the original, so they go last.
+        for entry in self.controls().filter(|entry| !entry.extra().is_empty()) {
+            file.seek(SeekFrom::Start(
+                entry.new_offset() + entry.diff().len() as u64,
+            ))?;
+            file.write_all(entry.extra())?;
+        }
+
+        file.set_len(self.new_size as u64)?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Returns, for every run, the other runs that overwrite bytes it reads, and
+/// for every run the number of other runs that read bytes it overwrites.
+fn hazards(runs: &[Run<'_>]) -> (Vec<Vec<usize>>, Vec<usize>) {
+    let mut writers = vec![Vec::new(); runs.len()];
+    let mut blockers = vec![0; runs.len()];
+
+    let mut by_read: Vec<usize> = (0..runs.len()).collect();
+    by_read.sort_by_key(|&i| runs[i].read);
+
+    // The furthest read end among the runs sorted before each one, so the scan
+    // below can stop once no earlier read reaches the written range.
+    let max_read_end: Vec<u64> = by_read
+        .iter()
+        .scan(0, |end, &i| {
+            *end = runs[i].read_end().max(*end);
+            Some(*end)
+        })
+        .collect();
+
+    for (i, run) in runs.iter().enumerate() {
+        let candidates = by_read.partition_point(|&j| runs[j].read < run.write_end());
+
+        for k in (0..candidates).rev() {
+            if max_read_end[k] <= run.write {
+                break;
+            }
+
+            let j = by_read[k];
+
+            if j != i && runs[j].read_end() > run.write {
+                writers[j].push(i);
+                blockers[i] += 1;
+            }
+        }
+    }
+
+    (writers, blockers)
+}
+
+/// Marks the bytes of run `i` as read, unblocking the runs that overwrite them.
+fn release(
+    i: usize,
+    writers: &[Vec<usize>],
+    blockers: &mut [usize],
+    read: &mut [bool],
+    queue: &mut VecDeque<usize>,
+) {
+    read[i] = true;
+
+    for &writer in &writers[i] {
+        blockers[writer] -= 1;
+
+        if blockers[writer] == 0 {
+            queue.push_back(writer);
+        }
+    }
+}
+
+/// Reads the original bytes of run `i` that its `writers` overwrite, merging
+/// overlapping ranges.
+fn read_overwritten<F>(
+    file: &mut F,
+    runs: &[Run<'_>],
+    i: usize,
+    writers: &[usize],
+) -> Result<Vec<Buffered>>
+where
+    F: Read + Seek + ?Sized,
+{
+    let run = &runs[i];
+    let mut ranges: Vec<Range<u64>> = writers
+        .iter()
+        .map(|&w| run.read.max(runs[w].write)..run.read_end().min(runs[w].write_end()))
+        .collect();
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<u64>> = Vec::new();
+
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|range| {
+            let mut bytes = vec![0; (range.end - range.start) as usize];
+
+            file.seek(SeekFrom::Start(range.start))?;
+            file.read_exact(&mut bytes)?;
+
+            Ok(Buffered {
+                offset: range.start,
+                bytes,
+            })
+        })
+        .collect()
+}
+
+/// Copies a run within `file` in chunks, back to front when it moves bytes
+/// forwards so that no chunk overwrites bytes still to be read. Bytes in
+/// `buffered` are taken from there instead of the file.
+fn copy<F>(file: &mut F, run: &Run<'_>, buffered: &[Buffered], chunk: &mut [u8]) -> Result<()>
+where
+    F: Read + Write + Seek + ?Sized,
+{
+    let len = run.diff.len();
+    let backwards = run.read < run.write;
+    let mut copied = 0;
+
+    while copied < len {
+        let n = (len - copied).min(chunk.len());
+        let offset = if backwards { len - copied - n } else { copied };
+        let chunk = &mut chunk[..n];
+
+        let start = run.read + offset as u64;
+
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+
+        for saved in buffered {
+            let from = saved.offset.max(start);
+            let to = (saved.offset + saved.bytes.len() as u64).min(start + n as u64);
+
+            if from < to {
+                chunk[(from - start) as usize..(to - start) as usize].copy_from_slice(
+                    &saved.bytes[(from - saved.offset) as usize..(to - saved.offset) as usize],
+                );
+            }
+        }
+
+        for (new, diff) in izip!(chunk.iter_mut(), &run.diff[offset..offset + n]) {
+            *new = new.wrapping_add(*diff);
+        }
+
+        file.seek(SeekFrom::Start(run.write + offset as u64))?;
+        file.write_all(chunk)?;
+
+        copied += n;
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use alloc::vec;
+
+    use crate::diff::tests::{edit, sample};
+    use crate::io::Cursor;
+    use crate::{Bsdiff4, Control, Error};
+
+    fn check(patch: &Bsdiff4, original: &[u8]) {
+        let mut file = Cursor::new(original.to_vec());
+
+        patch.apply_in_place(&mut file).unwrap();
+
+        assert_eq!(file.into_inner(), patch.apply_to_slice(original).unwrap());
+    }
+
+    #[test]
+    fn in_place_cycle() {
+        let original = sample(1_000, 10);
+
+        // Swaps the halves, with each run overwriting what the other reads.
+        let swap = Bsdiff4::from_parts(
+            1_000,
+            vec![
+                Control::new(0, 0, 500),
+                Control::new(500, 0, -1_000),
+                Control::new(500, 0, 0),
+            ],
+            sample(1_000, 11),
+            vec![],
+        )
+        .unwrap();
+
+        // The second run overwrites only part of what the first reads.
+        let partial = Bsdiff4::from_parts(
+            600,
+            vec![
+                Control::new(0, 0, 400),
+                Control::new(300, 0, -700),
+                Control::new(300, 0, 0),
+            ],
+            sample(600, 12),
+            vec![],
+        )
+        .unwrap();
+
+        check(&swap, &original);
+        check(&partial, &original);
+    }
+
+    #[test]
+    fn in_place_overlapping_runs() {
+        let original = sample(1_000, 12);
+
+        // Shifts forwards and backwards, so runs overlap what they read.
+        let insert = Bsdiff4::from_parts(
+            1_010,
+            vec![Control::new(0, 10, 0), Control::new(1_000, 0, 0)],
+            vec![1; 1_000],
+            vec![2; 10],
+        )
+        .unwrap();
+        let remove = Bsdiff4::from_parts(
+            900,
+            vec![Control::new(0, 0, 100), Control::new(900, 0, 0)],
+            vec![3; 900],
+            vec![],
+        )
+        .unwrap();
+
+        check(&insert, &original);
+        check(&remove, &original);
+    }
+
+    #[test]
+    fn in_place_round_trip() {
+        let old = sample(30_000, 13);
+        let new = edit(&old, 14);
+
+        check(&Bsdiff4::diff(&old, &new), &old);
+        check(&Bsdiff4::diff(&new, &old), &new);
+    }
+
+    #[test]
+    fn in_place_original_too_short() {
+        let patch =
+            Bsdiff4::from_parts(3, vec![Control::new(3, 0, 0)], vec![0; 3], vec![]).unwrap();
+        let mut file = Cursor::new(vec![1, 2]);
+
+        assert!(matches!(
+            patch.apply_in_place(&mut file),
+            Err(Error::OriginalTooShort { control: 0, .. })
+        ));
+        assert_eq!(file.into_inner(), [1, 2]);
+    }
+}
@@ -1,12 +1,86 @@
-use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
-use std::mem::size_of;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::mem::size_of;
+use core::ops::Range;
 
-use anyhow::{bail, ensure, Context, Result};
-use byteorder::{ReadBytesExt, LE};
-use bzip2::read::BzDecoder;
 use itertools::izip;
 
+#[cfg(feature = "async")]
+mod async_io;
+#[cfg(feature = "checksum")]
+pub mod checksum;
+mod compose;
+mod compression;
+mod control;
+mod decoder;
+mod diff;
+mod endsley;
+mod error;
+mod inplace;
+pub mod io;
+mod limits;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod provenance;
+mod reader;
+mod rollback;
+mod stream;
+mod validate;
+
+#[cfg(feature = "checksum")]
+use checksum::{Checksum, HashingWriter};
+pub use compression::Compression;
+pub use control::{Control, ControlEntry, Controls};
+pub use decoder::{Event, PatchDecoder};
+use endsley::{read_endsley_body, ENDSLEY_MAGIC};
+use error::decode_error;
+pub use error::{Block, Error, Result};
+pub use inplace::SetLen;
+use io::{Cursor, Read, Seek, SeekFrom, Write};
+pub use limits::Limits;
+pub use provenance::{Origin, Segment};
+pub use reader::PatchedReader;
+pub use stream::StreamingPatch;
+
 const MAGIC: &[u8] = b"BSDIFF40";
+const BSDF2_MAGIC: &[u8] = b"BSDF2";
+const BSDRS_MAGIC: &[u8] = b"BSDRS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The classic `BSDIFF40` layout with three separately compressed blocks.
+    Bsdiff40,
+    /// The `ENDSLEY/BSDIFF43` layout with a single interleaved stream.
+    Endsley,
+    /// Android's `BSDF2` layout, where each block declares its own compression.
+    Bsdf2,
+    /// This crate's `BSDRS` layout, which extends `BSDF2` with further compressors.
+    Bsdrs,
+}
+
+impl Format {
+    pub fn detect(magic: &[u8]) -> Option<Format> {
+        if magic.starts_with(MAGIC) {
+            Some(Format::Bsdiff40)
+        } else if magic.starts_with(ENDSLEY_MAGIC) {
+            Some(Format::Endsley)
+        } else if magic.starts_with(BSDF2_MAGIC) {
+            Some(Format::Bsdf2)
+        } else if magic.starts_with(BSDRS_MAGIC) {
+            Some(Format::Bsdrs)
+        } else {
+            None
+        }
+    }
+}
 
 pub struct Bsdiff4 {
     new_size: usize,
@@ -17,58 +91,131 @@ pub struct Bsdiff4 {
 
 impl Bsdiff4 {
     pub fn read<R: Read>(r: &mut R) -> Result<Self> {
-        {
-            let mut magic = [0; MAGIC.len()];
+        Self::read_with_limits(r, &Limits::default())
+    }
 
-            r.read_exact(&mut magic)
-                .context("failed to read bsdiff4 magic")?;
+    pub fn read_with_limits<R: Read>(r: &mut R, limits: &Limits) -> Result<Self> {
+        let header = read_header(r)?;
+        let new_size = header.checked_new_size(limits)?;
+
+        let patch = match header {
+            Header::Blocks {
+                compression: [control_compression, diff_compression, extra_compression],
+                len_control,
+                len_diff,
+                ..
+            } => {
+                let control = read_control_block(r, len_control, control_compression, limits)?;
+                let diff = read_block(r, len_diff, diff_compression, Block::Diff, limits)?;
+                let extra = read_block_to_end(r, extra_compression, Block::Extra, limits)?;
+
+                Bsdiff4 {
+                    new_size,
+                    control,
+                    diff,
+                    extra,
+                }
+            }
+            Header::Endsley { .. } => read_endsley_body(r, new_size, limits)?,
+        };
 
-            ensure!(magic == MAGIC, "invalid bsdiff4 magic");
-        }
+        patch.validate()?;
 
-        let len_control = read_positive_le_i64(r).context("failed to read `len_control`")?;
-        let len_diff = read_positive_le_i64(r).context("failed to read `len_diff`")?;
-        let new_size = read_positive_le_i64(r).context("failed to read `new_size`")?;
-        let new_size = usize::try_from(new_size)?;
+        Ok(patch)
+    }
 
-        dbg!(len_control, len_diff, new_size);
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        self.write_with_level(w, 9)
+    }
 
-        let control = read_control_block(r, len_control).context("failed to read `bcontrol`")?;
-        let diff = read_bzip_block(r, len_diff)?;
-        let extra = read_bzip_to_end(r)?;
+    pub fn write_with_level<W: Write>(&self, w: &mut W, level: u32) -> Result<()> {
+        self.write_blocks(w, MAGIC, Compression::Bzip2, Some(level))
+    }
 
-        eprintln!("{:#?}", control.iter().take(10).collect::<Vec<_>>());
+    /// Writes the patch in the `BSDRS` layout with every block compressed by
+    /// `compression`, at its default level unless `level` is given.
+    pub fn write_compressed<W: Write>(
+        &self,
+        w: &mut W,
+        compression: Compression,
+        level: Option<u32>,
+    ) -> Result<()> {
+        let mut magic = [compression.id(); MAGIC.len()];
+        magic[..BSDRS_MAGIC.len()].copy_from_slice(BSDRS_MAGIC);
+
+        self.write_blocks(w, &magic, compression, level)
+    }
 
-        Ok(Bsdiff4 {
-            new_size,
-            control,
-            diff,
-            extra,
-        })
+    fn write_blocks<W: Write>(
+        &self,
+        w: &mut W,
+        magic: &[u8],
+        compression: Compression,
+        level: Option<u32>,
+    ) -> Result<()> {
+        let control = compression.encode(&encode_control_block(&self.control)?, level)?;
+        let diff = compression.encode(&self.diff, level)?;
+        let extra = compression.encode(&self.extra, level)?;
+
+        w.write_all(magic)?;
+
+        write_positive_le_i64(w, control.len() as u64, "len_control")?;
+        write_positive_le_i64(w, diff.len() as u64, "len_diff")?;
+        write_positive_le_i64(w, self.new_size as u64, "new_size")?;
+
+        w.write_all(&control)?;
+        w.write_all(&diff)?;
+        w.write_all(&extra)?;
+
+        Ok(())
+    }
+
+    pub fn new_size(&self) -> usize {
+        self.new_size
+    }
+
+    pub fn control_count(&self) -> usize {
+        self.control.len()
+    }
+
+    pub fn diff_len(&self) -> usize {
+        self.diff.len()
+    }
+
+    pub fn extra_len(&self) -> usize {
+        self.extra.len()
     }
 
     pub fn apply(&self, original: &mut (impl Read + Seek), new: &mut impl Write) -> Result<()> {
-        let new_chunk = &mut Vec::new();
-        let diff_chunk = &mut Vec::new();
         let diff = &mut Cursor::new(&self.diff);
         let extra = &mut Cursor::new(&self.extra);
+        let state = &mut ApplyState::new(original.stream_position()?);
 
         for control in &self.control {
-            new_chunk.clear();
-            diff_chunk.clear();
+            apply_control(control, original, diff, extra, new, state)?;
+        }
 
-            copy_exact(original, new_chunk, control.diff_amount)?;
-            copy_exact(diff, diff_chunk, control.diff_amount)?;
+        Ok(())
+    }
 
-            for (orig, diff) in izip!(new_chunk.iter_mut(), diff_chunk.iter()) {
-                *orig = orig.wrapping_add(*diff);
-            }
+    #[cfg(feature = "checksum")]
+    pub fn apply_verified(
+        &self,
+        original: &mut (impl Read + Seek),
+        new: &mut impl Write,
+        expected: &Checksum,
+    ) -> Result<()> {
+        let mut new = HashingWriter::new(new, expected.algorithm());
 
-            io::copy(&mut new_chunk.as_slice(), new)?;
+        self.apply(original, &mut new)?;
 
-            copy_exact(extra, new, control.extra_amount)?;
+        let actual = new.finish();
 
-            original.seek(SeekFrom::Current(control.seek))?;
+        if actual != *expected {
+            return Err(Error::ChecksumMismatch {
+                expected: expected.clone(),
+                actual,
+            });
         }
 
         Ok(())
@@ -81,69 +228,543 @@ impl Bsdiff4 {
 
         Ok(new)
     }
+
+    /// Applies the patch from and into memory, for example memory-mapped files.
+    ///
+    /// `new` must be exactly [`new_size`](Self::new_size) bytes long.
+    pub fn apply_to_buffer(&self, original: &[u8], new: &mut [u8]) -> Result<()> {
+        let end = self.apply_controls(0..self.control.len(), Offsets::default(), original, new)?;
+
+        if end.new != new.len() {
+            return Err(Error::NewSizeMismatch {
+                expected: new.len() as u64,
+                actual: end.new as u64,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Applies `controls` starting at `start`, writing into `new`, which begins at
+    /// `start.new` in the output.
+    fn apply_controls(
+        &self,
+        controls: Range<usize>,
+        start: Offsets,
+        original: &[u8],
+        new: &mut [u8],
+    ) -> Result<Offsets> {
+        let new_len = new.len();
+        let mut offsets = start;
+
+        for control in &self.control[controls] {
+            let block_too_short = |block, new_offset: usize| Error::BlockTooShort {
+                block,
+                control: offsets.control,
+                new_offset: new_offset as u64,
+            };
+
+            let len = control.diff_amount as usize;
+            // Like `apply`, only look at the original when there is a diff to add.
+            let original_chunk = if len == 0 {
+                &[]
+            } else {
+                usize::try_from(offsets.original)
+                    .ok()
+                    .and_then(|start| original.get(start..start.checked_add(len)?))
+                    .ok_or(Error::OriginalTooShort {
+                        control: offsets.control,
+                        original_offset: offsets.original,
+                    })?
+            };
+            let diff_chunk = self
+                .diff
+                .get(offsets.diff..)
+                .and_then(|diff| diff.get(..len))
+                .ok_or(block_too_short(Block::Diff, offsets.new))?;
+            let new_chunk = new
+                .get_mut(offsets.new - start.new..)
+                .and_then(|new| new.get_mut(..len))
+                .ok_or(Error::NewSizeMismatch {
+                    expected: (start.new + new_len) as u64,
+                    actual: (offsets.new + len) as u64,
+                })?;
+
+            for (new, orig, diff) in izip!(new_chunk, original_chunk, diff_chunk) {
+                *new = orig.wrapping_add(*diff);
+            }
+
+            offsets.diff += len;
+            offsets.new += len;
+            offsets.original += len as u64;
+
+            let len = control.extra_amount as usize;
+            let extra_chunk = self
+                .extra
+                .get(offsets.extra..)
+                .and_then(|extra| extra.get(..len))
+                .ok_or(block_too_short(Block::Extra, offsets.new))?;
+
+            new.get_mut(offsets.new - start.new..)
+                .and_then(|new| new.get_mut(..len))
+                .ok_or(Error::NewSizeMismatch {
+                    expected: (start.new + new_len) as u64,
+                    actual: (offsets.new + len) as u64,
+                })?
+                .copy_from_slice(extra_chunk);
+
+            offsets.extra += len;
+            offsets.new += len;
+
+            offsets.original =
+                offsets
+                    .original
+                    .checked_add_signed(control.seek)
+                    .ok_or(Error::InvalidSeek {
+                        control: offsets.control,
+                        original_offset: offsets.original,
+                        seek: control.seek,
+                    })?;
+            offsets.control += 1;
+        }
+
+        Ok(offsets)
+    }
+}
+
+/// Where a control entry starts reading and writing.
+#[derive(Debug, Clone, Copy, Default)]
+struct Offsets {
+    control: usize,
+    original: u64,
+    diff: usize,
+    extra: usize,
+    new: usize,
 }
 
-fn read_control_block(r: &mut impl Read, len: u64) -> Result<Vec<Control>> {
-    const CONTROL_SIZE: usize = 3 * size_of::<u64>();
-    let block = read_bzip_block(r, len)?;
+enum Header {
+    Blocks {
+        compression: [Compression; 3],
+        len_control: u64,
+        len_diff: u64,
+        new_size: u64,
+    },
+    Endsley {
+        new_size: u64,
+    },
+}
 
-    if (block.len() % CONTROL_SIZE) != 0 {
-        bail!("invalid control block size");
+impl Header {
+    fn new_size(&self) -> u64 {
+        match *self {
+            Header::Blocks { new_size, .. } | Header::Endsley { new_size } => new_size,
+        }
     }
 
-    let num_control = block.len() / CONTROL_SIZE;
-    let mut control_block = Vec::with_capacity(num_control);
+    fn checked_new_size(&self, limits: &Limits) -> Result<usize> {
+        let new_size = self.new_size();
 
-    let mut block = Cursor::new(block);
+        if new_size > limits.max_new_size {
+            return Err(Error::NewSizeTooLarge {
+                new_size,
+                max: limits.max_new_size,
+            });
+        }
 
-    for _ in 0..num_control {
-        let control = Control {
-            diff_amount: read_positive_le_i64(&mut block)?,
-            extra_amount: read_positive_le_i64(&mut block)?,
-            seek: read_ones_complement_le_i64(&mut block)?,
-        };
+        usize::try_from(new_size).map_err(|_| Error::LengthOutOfRange {
+            field: "new_size",
+            value: new_size,
+        })
+    }
+}
 
-        control_block.push(control)
+fn read_header(r: &mut impl Read) -> Result<Header> {
+    let read_header_exact = |r: &mut dyn Read, buf: &mut [u8]| {
+        r.read_exact(buf).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::TruncatedHeader,
+            _ => Error::Io(err),
+        })
+    };
+
+    let mut magic = [0; ENDSLEY_MAGIC.len()];
+    read_header_exact(r, &mut magic[..MAGIC.len()])?;
+
+    if magic.starts_with(&ENDSLEY_MAGIC[..MAGIC.len()]) {
+        read_header_exact(r, &mut magic[MAGIC.len()..])?;
     }
 
-    Ok(control_block)
+    let compression = match Format::detect(&magic) {
+        Some(Format::Bsdiff40) => [Compression::Bzip2; 3],
+        Some(Format::Bsdf2) => {
+            let ids = &magic[BSDF2_MAGIC.len()..MAGIC.len()];
+
+            [
+                Compression::from_bsdf2(ids[0])?,
+                Compression::from_bsdf2(ids[1])?,
+                Compression::from_bsdf2(ids[2])?,
+            ]
+        }
+        Some(Format::Bsdrs) => {
+            let ids = &magic[BSDRS_MAGIC.len()..MAGIC.len()];
+
+            [
+                Compression::from_id(ids[0])?,
+                Compression::from_id(ids[1])?,
+                Compression::from_id(ids[2])?,
+            ]
+        }
+        Some(Format::Endsley) => {
+            let mut header = [0; size_of::<u64>()];
+            read_header_exact(r, &mut header)?;
+
+            let new_size = read_positive_le_i64(&mut header.as_slice(), "new_size")?;
+
+            return Ok(Header::Endsley { new_size });
+        }
+        None => return Err(Error::InvalidMagic),
+    };
+
+    let mut header = [0; 3 * size_of::<u64>()];
+    read_header_exact(r, &mut header)?;
+
+    let mut header = header.as_slice();
+    let len_control = read_positive_le_i64(&mut header, "len_control")?;
+    let len_diff = read_positive_le_i64(&mut header, "len_diff")?;
+    let new_size = read_positive_le_i64(&mut header, "new_size")?;
+
+    Ok(Header::Blocks {
+        compression,
+        len_control,
+        len_diff,
+        new_size,
+    })
 }
 
-#[derive(Debug)]
-struct Control {
-    diff_amount: u64,
-    extra_amount: u64,
-    seek: i64,
+const CHUNK_SIZE: usize = 64 * 1024;
+
+struct ApplyState {
+    original_chunk: Box<[u8]>,
+    diff_chunk: Box<[u8]>,
+    control: usize,
+    original_offset: u64,
+    new_offset: u64,
 }
 
-fn read_bzip_block(r: &mut impl Read, len: u64) -> Result<Vec<u8>> {
-    let mut block = Vec::with_capacity(len as usize);
-    BzDecoder::new(r.take(len)).read_to_end(&mut block)?;
+impl ApplyState {
+    fn new(original_offset: u64) -> Self {
+        ApplyState {
+            original_chunk: vec![0; CHUNK_SIZE].into_boxed_slice(),
+            diff_chunk: vec![0; CHUNK_SIZE].into_boxed_slice(),
+            control: 0,
+            original_offset,
+            new_offset: 0,
+        }
+    }
 
-    Ok(block)
+    fn block_too_short(&self, block: Block) -> Error {
+        Error::BlockTooShort {
+            block,
+            control: self.control,
+            new_offset: self.new_offset,
+        }
+    }
 }
 
-fn read_bzip_to_end(r: &mut impl Read) -> Result<Vec<u8>> {
-    let mut block = Vec::new();
-    BzDecoder::new(r).read_to_end(&mut block)?;
+fn apply_control<R, D, E, W>(
+    control: &Control,
+    original: &mut R,
+    diff: &mut D,
+    extra: &mut E,
+    new: &mut W,
+    state: &mut ApplyState,
+) -> Result<()>
+where
+    R: Read + Seek + ?Sized,
+    D: Read + ?Sized,
+    E: Read + ?Sized,
+    W: Write + ?Sized,
+{
+    let mut remaining = control.diff_amount;
+
+    while remaining > 0 {
+        let len = remaining.min(CHUNK_SIZE as u64) as usize;
+        let new_chunk = &mut state.original_chunk[..len];
+        let diff_chunk = &mut state.diff_chunk[..len];
+
+        if read_full(original, new_chunk)? < len {
+            return Err(Error::OriginalTooShort {
+                control: state.control,
+                original_offset: state.original_offset,
+            });
+        }
+
+        if read_full(diff, diff_chunk).map_err(|err| decode_error(Block::Diff, err))? < len {
+            return Err(state.block_too_short(Block::Diff));
+        }
+
+        for (orig, diff) in izip!(new_chunk.iter_mut(), diff_chunk.iter()) {
+            *orig = orig.wrapping_add(*diff);
+        }
+
+        new.write_all(new_chunk)?;
+
+        remaining -= len as u64;
+        state.original_offset += len as u64;
+        state.new_offset += len as u64;
+    }
+
+    let mut remaining = control.extra_amount;
+
+    while remaining > 0 {
+        let len = remaining.min(CHUNK_SIZE as u64) as usize;
+        let extra_chunk = &mut state.diff_chunk[..len];
+
+        if read_full(extra, extra_chunk).map_err(|err| decode_error(Block::Extra, err))? < len {
+            return Err(state.block_too_short(Block::Extra));
+        }
+
+        new.write_all(extra_chunk)?;
+
+        remaining -= len as u64;
+        state.new_offset += len as u64;
+    }
+
+    if state
+        .original_offset
+        .checked_add_signed(control.seek)
+        .is_none()
+    {
+        return Err(Error::InvalidSeek {
+            control: state.control,
+            original_offset: state.original_offset,
+            seek: control.seek,
+        });
+    }
+
+    state.original_offset = original.seek(SeekFrom::Current(control.seek))?;
+    state.control += 1;
+
+    Ok(())
+}
+
+fn read_full<R: Read + ?Sized>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+fn read_control_block(
+    r: &mut impl Read,
+    len: u64,
+    compression: Compression,
+    limits: &Limits,
+) -> Result<Vec<Control>> {
+    let block = read_block(r, len, compression, Block::Control, limits)?;
+
+    decode_control_block(&block, limits)
+}
+
+fn decode_control_block(block: &[u8], limits: &Limits) -> Result<Vec<Control>> {
+    if !block.len().is_multiple_of(CONTROL_SIZE) {
+        return Err(Error::InvalidControlBlockSize {
+            len: block.len() as u64,
+        });
+    }
+
+    let num_control = (block.len() / CONTROL_SIZE) as u64;
+
+    if num_control > limits.max_controls {
+        return Err(Error::TooManyControls {
+            count: num_control,
+            max: limits.max_controls,
+        });
+    }
+
+    block
+        .chunks_exact(CONTROL_SIZE)
+        .enumerate()
+        .map(|(index, mut control)| read_control(&mut control, index))
+        .collect()
+}
+
+fn encode_control_block(control_block: &[Control]) -> Result<Vec<u8>> {
+    let mut block = Vec::with_capacity(control_block.len() * CONTROL_SIZE);
+
+    for control in control_block {
+        write_control(&mut block, control)?;
+    }
 
     Ok(block)
 }
 
+fn write_control(w: &mut impl Write, control: &Control) -> Result<()> {
+    write_positive_le_i64(w, control.diff_amount, "diff_amount")?;
+    write_positive_le_i64(w, control.extra_amount, "extra_amount")?;
+    write_ones_complement_le_i64(w, control.seek)?;
+
+    Ok(())
+}
+
+const CONTROL_SIZE: usize = 3 * size_of::<u64>();
+
+fn read_control(r: &mut impl Read, index: usize) -> Result<Control> {
+    let positive = |field, value: i64| {
+        u64::try_from(value).map_err(|_| Error::NegativeControlLength {
+            control: index,
+            field,
+            value,
+        })
+    };
+
+    Ok(Control {
+        diff_amount: positive("diff_amount", read_le_i64(r)?)?,
+        extra_amount: positive("extra_amount", read_le_i64(r)?)?,
+        seek: read_ones_complement_le_i64(r)?,
+    })
+}
+
+fn read_block(
+    r: &mut impl Read,
+    len: u64,
+    compression: Compression,
+    block: Block,
+    limits: &Limits,
+) -> Result<Vec<u8>> {
+    read_block_limited(r.take(len), compression, block, limits)
+}
+
+fn read_block_to_end(
+    r: &mut impl Read,
+    compression: Compression,
+    block: Block,
+    limits: &Limits,
+) -> Result<Vec<u8>> {
+    read_block_limited(r, compression, block, limits)
+}
+
+fn read_block_limited(
+    r: impl Read,
+    compression: Compression,
+    block: Block,
+    limits: &Limits,
+) -> Result<Vec<u8>> {
+    struct Counter<'a, R> {
+        reader: R,
+        bytes_read: &'a Cell<u64>,
+    }
+
+    impl<R: Read> Read for Counter<'_, R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let bytes_read = self.reader.read(buf)?;
+
+            self.bytes_read
+                .set(self.bytes_read.get() + bytes_read as u64);
+
+            Ok(bytes_read)
+        }
+    }
+
+    let compressed = Cell::new(0);
+    let mut decoder = compression.decoder(Counter {
+        reader: r,
+        bytes_read: &compressed,
+    })?;
+    let mut data = Vec::new();
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    loop {
+        let len = match decoder.read(&mut chunk) {
+            Ok(0) => return Ok(data),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(decode_error(block, err)),
+        };
+
+        check_block_size(block, (data.len() + len) as u64, compressed.get(), limits)?;
+
+        data.extend_from_slice(&chunk[..len]);
+    }
+}
+
+/// Checks `decompressed` bytes of `block`, decoded from `compressed` bytes,
+/// against `limits`.
+fn check_block_size(
+    block: Block,
+    decompressed: u64,
+    compressed: u64,
+    limits: &Limits,
+) -> Result<()> {
+    if decompressed > limits.max_block_size {
+        return Err(Error::BlockTooLarge {
+            block,
+            max: limits.max_block_size,
+        });
+    }
+
+    // Checked as the control block is decoded, so that it is never held whole.
+    let controls = decompressed / CONTROL_SIZE as u64;
+
+    if block == Block::Control && controls > limits.max_controls {
+        return Err(Error::TooManyControls {
+            count: controls,
+            max: limits.max_controls,
+        });
+    }
+
+    if decompressed > compressed.saturating_mul(limits.max_expansion_ratio) {
+        return Err(Error::ExpansionRatioExceeded {
+            block,
+            max: limits.max_expansion_ratio,
+        });
+    }
+
+    Ok(())
+}
+
+fn read_le_i64(r: &mut impl Read) -> Result<i64> {
+    let mut buf = [0; size_of::<i64>()];
+    r.read_exact(&mut buf)?;
+
+    Ok(i64::from_le_bytes(buf))
+}
+
 fn read_ones_complement_le_i64(r: &mut impl Read) -> Result<i64> {
-    let n = r.read_i64::<LE>()?;
+    let n = read_le_i64(r)?;
     let n = ones_complement_i64(n);
 
     Ok(n)
 }
 
-fn read_positive_le_i64(r: &mut impl Read) -> Result<u64> {
-    let n = r.read_i64::<LE>()?;
-    let n = u64::try_from(n)?;
+fn read_positive_le_i64(r: &mut impl Read, field: &'static str) -> Result<u64> {
+    let n = read_le_i64(r)?;
+    let n = u64::try_from(n).map_err(|_| Error::NegativeLength { field, value: n })?;
 
     Ok(n)
 }
 
+fn write_ones_complement_le_i64(w: &mut impl Write, n: i64) -> Result<()> {
+    let n = ones_complement_i64(n);
+    w.write_all(&n.to_le_bytes())?;
+
+    Ok(())
+}
+
+fn write_positive_le_i64(w: &mut impl Write, n: u64, field: &'static str) -> Result<()> {
+    let n = i64::try_from(n).map_err(|_| Error::LengthOutOfRange { field, value: n })?;
+    w.write_all(&n.to_le_bytes())?;
+
+    Ok(())
+}
+
 const fn ones_complement_i64(y: i64) -> i64 {
     y & i64::MIN | y.wrapping_abs()
 }
@@ -153,39 +774,82 @@ where
     R: Read + ?Sized,
     W: Write + ?Sized,
 {
-    struct Meter<W> {
-        writer: W,
-        bytes_written: usize,
-    }
+    let mut chunk = vec![0; amount.min(CHUNK_SIZE as u64) as usize];
+    let mut remaining = amount;
 
-    impl<W> Write for Meter<W>
-    where
-        W: Write,
-    {
-        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-            let bytes_written = self.writer.write(buf)?;
+    while remaining > 0 {
+        let len = remaining.min(chunk.len() as u64) as usize;
+        let bytes_read = read_full(reader, &mut chunk[..len])?;
 
-            self.bytes_written += bytes_written;
+        writer.write_all(&chunk[..bytes_read])?;
 
-            Ok(bytes_written)
+        if bytes_read < len {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "copied less bytes than expected",
+            )));
         }
 
-        fn flush(&mut self) -> io::Result<()> {
-            self.writer.flush()
-        }
+        remaining -= len as u64;
     }
 
-    let mut reader = reader.take(amount);
-    let mut writer = Meter {
-        writer,
-        bytes_written: 0,
-    };
+    Ok(())
+}
 
-    io::copy(&mut reader, &mut writer)?;
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::diff::tests::{edit, sample};
+    use crate::{Bsdiff4, Compression};
+
+    #[test]
+    fn read_write_round_trip() {
+        let old = sample(20_000, 17);
+        let new = edit(&old, 18);
+        let patch = Bsdiff4::diff(&old, &new);
+
+        let compressions = [
+            Compression::Raw,
+            #[cfg(feature = "bzip2")]
+            Compression::Bzip2,
+            #[cfg(feature = "brotli")]
+            Compression::Brotli,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd,
+            #[cfg(feature = "xz")]
+            Compression::Xz,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+        ];
+
+        for compression in compressions {
+            let mut encoded = Vec::new();
+            patch
+                .write_compressed(&mut encoded, compression, None)
+                .unwrap();
+
+            let read = Bsdiff4::read(&mut &encoded[..]).unwrap();
+            let mut rewritten = Vec::new();
+            read.write_compressed(&mut rewritten, compression, None)
+                .unwrap();
+
+            assert_eq!(read.apply_to_slice(&old).unwrap(), new);
+            assert_eq!(rewritten, encoded);
+        }
 
-    let bytes_written = u64::try_from(writer.bytes_written)?;
+        #[cfg(feature = "bzip2")]
+        {
+            let mut encoded = Vec::new();
+            patch.write(&mut encoded).unwrap();
 
-    ensure!(bytes_written == amount, "copied less bytes than expected");
+            let mut rewritten = Vec::new();
+            Bsdiff4::read(&mut &encoded[..])
+                .unwrap()
+                .write(&mut rewritten)
+                .unwrap();
 
-    Ok(())
+            assert_eq!(rewritten, encoded);
+        }
+    }
 }
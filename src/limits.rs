@@ -0,0 +1,128 @@
+/// Resource limits applied while reading a patch from an untrusted source.
+///
+/// The default imposes no limits.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Largest `new_size` the header may declare.
+    pub max_new_size: u64,
+    /// Largest decompressed size of each of the control, diff and extra blocks.
+    pub max_block_size: u64,
+    /// Largest number of control entries.
+    pub max_controls: u64,
+    /// Largest ratio of decompressed to compressed bytes within a block.
+    pub max_expansion_ratio: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_new_size: u64::MAX,
+            max_block_size: u64::MAX,
+            max_controls: u64::MAX,
+            max_expansion_ratio: u64::MAX,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "bzip2"))]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::Limits;
+    use crate::{Block, Bsdiff4, Control, Error};
+
+    /// A patch of `len` zeros from the diff block, which bzip2 shrinks to a
+    /// tiny fraction.
+    fn zeros(len: usize) -> Vec<u8> {
+        let patch = Bsdiff4::from_parts(
+            len,
+            vec![Control::new(len as u64, 0, 0)],
+            vec![0; len],
+            vec![],
+        )
+        .unwrap();
+
+        let mut encoded = Vec::new();
+        patch.write(&mut encoded).unwrap();
+
+        encoded
+    }
+
+    #[test]
+    fn limit_expansion_ratio() {
+        let limits = Limits {
+            max_expansion_ratio: 100,
+            ..Limits::default()
+        };
+
+        assert!(matches!(
+            Bsdiff4::read_with_limits(&mut &zeros(8 << 20)[..], &limits),
+            Err(Error::ExpansionRatioExceeded {
+                block: Block::Diff,
+                max: 100,
+            })
+        ));
+    }
+
+    #[test]
+    fn limit_new_size() {
+        // A header declaring far more than could be allocated.
+        let mut patch = zeros(0);
+        patch[24..32].copy_from_slice(&(i64::MAX as u64).to_le_bytes());
+
+        let limits = Limits {
+            max_new_size: 1 << 20,
+            ..Limits::default()
+        };
+
+        assert!(matches!(
+            Bsdiff4::read_with_limits(&mut &patch[..], &limits),
+            Err(Error::NewSizeTooLarge {
+                new_size,
+                max: 0x10_0000,
+            }) if new_size == i64::MAX as u64
+        ));
+    }
+
+    #[test]
+    fn limit_block_size() {
+        let limits = Limits {
+            max_block_size: 1 << 20,
+            ..Limits::default()
+        };
+
+        assert!(matches!(
+            Bsdiff4::read_with_limits(&mut &zeros(8 << 20)[..], &limits),
+            Err(Error::BlockTooLarge {
+                block: Block::Diff,
+                max: 0x10_0000,
+            })
+        ));
+    }
+
+    #[test]
+    fn limit_controls() {
+        let patch = Bsdiff4::from_parts(
+            100_000,
+            vec![Control::new(1, 0, 0); 100_000],
+            vec![0; 100_000],
+            vec![],
+        )
+        .unwrap();
+
+        let mut encoded = Vec::new();
+        patch.write(&mut encoded).unwrap();
+
+        let limits = Limits {
+            max_controls: 10,
+            ..Limits::default()
+        };
+
+        // Counted while decoding, so well before all the entries are read.
+        assert!(matches!(
+            Bsdiff4::read_with_limits(&mut &encoded[..], &limits),
+            Err(Error::TooManyControls { count, max: 10 }) if count < 100_000
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::{Bsdiff4, Result};
+
+/// File-backed variants that memory-map their inputs and output.
+///
+/// The mapped files must not be modified by anyone else while in use.
+impl Bsdiff4 {
+    /// Applies the patch to the file at `original_path`, writing the new file
+    /// through a mapping of a temporary file that then replaces `output_path`.
+    ///
+    /// `output_path` may name the original itself.
+    pub fn apply_mmap(
+        &self,
+        original_path: impl AsRef<Path>,
+        output_path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let output_path = output_path.as_ref();
+        let original = map(&File::open(original_path)?)?;
+
+        let (temp_path, temp) = create_temp(output_path)?;
+        let result = self.write_mapped(original.as_deref().unwrap_or_default(), temp, output_path);
+
+        drop(original);
+
+        match result.and_then(|()| Ok(fs::rename(&temp_path, output_path)?)) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(err)
+            }
+        }
+    }
+
+    /// Diffs the files at `old_path` and `new_path` through read-only mappings.
+    pub fn diff_mmap(old_path: impl AsRef<Path>, new_path: impl AsRef<Path>) -> Result<Self> {
+        let old = map(&File::open(old_path)?)?;
+        let new = map(&File::open(new_path)?)?;
+
+        Ok(Bsdiff4::diff(
+            old.as_deref().unwrap_or_default(),
+            new.as_deref().unwrap_or_default(),
+        ))
+    }
+
+    /// Writes the new file to `output`, with the permissions of `output_path`
+    /// if it exists.
+    fn write_mapped(&self, original: &[u8], output: File, output_path: &Path) -> Result<()> {
+        if let Ok(metadata) = fs::metadata(output_path) {
+            output.set_permissions(metadata.permissions())?;
+        }
+
+        output.set_len(self.new_size as u64)?;
+
+        if self.new_size == 0 {
+            return Ok(());
+        }
+
+        // SAFETY: the temporary file was created by this call for its own use.
+        let mut new = unsafe { MmapMut::map_mut(&output)? };
+
+        self.apply_to_buffer(original, &mut new)?;
+
+        new.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Creates a new file next to `path` to be renamed over it.
+fn create_temp(path: &Path) -> io::Result<(PathBuf, File)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path.file_name().unwrap_or_default();
+
+    loop {
+        let mut temp_name = OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(
+            ".{}.{}.tmp",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let temp_path = dir.join(temp_name);
+
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Maps a whole file read-only, or returns `None` for an empty file, which
+/// cannot be mapped on every platform.
+fn map(file: &File) -> Result<Option<Mmap>> {
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+
+    // SAFETY: the file must not be modified while mapped, as documented above.
+    Ok(Some(unsafe { Mmap::map(file)? }))
+}
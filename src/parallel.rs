@@ -0,0 +1,169 @@
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::panic;
+use std::thread;
+
+use crate::endsley::read_endsley_body;
+use crate::{read_block, read_block_to_end, read_control_block, read_header};
+use crate::{Block, Bsdiff4, Control, Error, Header, Limits, Offsets, Result};
+
+impl Bsdiff4 {
+    /// Like [`Bsdiff4::read`], but decompresses the control, diff and extra
+    /// blocks on separate threads.
+    ///
+    /// The compressed blocks are read into memory first. `ENDSLEY/BSDIFF43`
+    /// patches have a single stream and are decoded sequentially.
+    pub fn read_parallel<R: Read>(r: &mut R) -> Result<Self> {
+        Self::read_parallel_with_limits(r, &Limits::default())
+    }
+
+    pub fn read_parallel_with_limits<R: Read>(r: &mut R, limits: &Limits) -> Result<Self> {
+        let header = read_header(r)?;
+        let new_size = header.checked_new_size(limits)?;
+
+        let patch = match header {
+            Header::Blocks {
+                compression: [control_compression, diff_compression, extra_compression],
+                len_control,
+                len_diff,
+                ..
+            } => {
+                let control = read_compressed(r, len_control)?;
+                let diff = read_compressed(r, len_diff)?;
+                let mut extra = Vec::new();
+                r.read_to_end(&mut extra)?;
+
+                let (control, diff, extra) = thread::scope(|s| {
+                    let control = s.spawn(|| {
+                        let r = &mut control.as_slice();
+                        read_control_block(r, len_control, control_compression, limits)
+                    });
+                    let diff = s.spawn(|| {
+                        let r = &mut diff.as_slice();
+                        read_block(r, len_diff, diff_compression, Block::Diff, limits)
+                    });
+                    let extra = {
+                        let r = &mut extra.as_slice();
+                        read_block_to_end(r, extra_compression, Block::Extra, limits)
+                    };
+
+                    (join(control), join(diff), extra)
+                });
+
+                Bsdiff4 {
+                    new_size,
+                    control: control?,
+                    diff: diff?,
+                    extra: extra?,
+                }
+            }
+            Header::Endsley { .. } => read_endsley_body(r, new_size, limits)?,
+        };
+
+        patch.validate()?;
+
+        Ok(patch)
+    }
+
+    /// Like [`Bsdiff4::apply_to_buffer`], but rebuilds disjoint ranges of `new`
+    /// on one thread per available core.
+    ///
+    /// The control entries are split where the output reaches each thread's
+    /// share, using running offsets into the original, diff and extra data.
+    pub fn apply_parallel(&self, original: &[u8], new: &mut [u8]) -> Result<()> {
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let parts = self.partition(new.len(), threads);
+
+        let ends = thread::scope(|s| {
+            let mut rest = &mut *new;
+            let mut handles = Vec::with_capacity(parts.len());
+
+            for (index, &start) in parts.iter().enumerate() {
+                let end = parts.get(index + 1);
+                let len = end.map_or(rest.len(), |end| end.new - start.new);
+                let (chunk, tail) = rest.split_at_mut(len);
+                let controls = start.control..end.map_or(self.control.len(), |end| end.control);
+
+                rest = tail;
+                handles
+                    .push(s.spawn(move || self.apply_controls(controls, start, original, chunk)));
+            }
+
+            handles.into_iter().map(join).collect::<Result<Vec<_>>>()
+        })?;
+
+        let end = ends.last().map_or(0, |end| end.new);
+
+        if end != new.len() {
+            return Err(Error::NewSizeMismatch {
+                expected: new.len() as u64,
+                actual: end as u64,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Splits the control entries into up to `parts` runs producing about the
+    /// same amount of output, and returns where each run starts.
+    ///
+    /// Splitting stops early at an entry that is out of bounds, so that the
+    /// last run reports the same error a sequential apply would.
+    fn partition(&self, new_len: usize, parts: usize) -> Vec<Offsets> {
+        let part_len = new_len.div_ceil(parts).max(1);
+        let mut starts = vec![Offsets::default()];
+        let mut offsets = Offsets::default();
+
+        for control in &self.control {
+            if offsets.new >= starts.len() * part_len && offsets.new <= new_len {
+                starts.push(offsets);
+            }
+
+            match self.advance(offsets, control) {
+                Some(next) => offsets = next,
+                None => break,
+            }
+        }
+
+        starts
+    }
+
+    fn advance(&self, offsets: Offsets, control: &Control) -> Option<Offsets> {
+        let diff_amount = usize::try_from(control.diff_amount).ok()?;
+        let extra_amount = usize::try_from(control.extra_amount).ok()?;
+
+        let diff = offsets
+            .diff
+            .checked_add(diff_amount)
+            .filter(|&diff| diff <= self.diff.len())?;
+        let extra = offsets
+            .extra
+            .checked_add(extra_amount)
+            .filter(|&extra| extra <= self.extra.len())?;
+        let original = offsets
+            .original
+            .checked_add(control.diff_amount)?
+            .checked_add_signed(control.seek)?;
+
+        Some(Offsets {
+            control: offsets.control + 1,
+            original,
+            diff,
+            extra,
+            new: diff + extra,
+        })
+    }
+}
+
+fn join<T>(handle: thread::ScopedJoinHandle<'_, T>) -> T {
+    handle
+        .join()
+        .unwrap_or_else(|err| panic::resume_unwind(err))
+}
+
+fn read_compressed(r: &mut impl Read, len: u64) -> Result<Vec<u8>> {
+    let mut block = Vec::new();
+    r.take(len).read_to_end(&mut block)?;
+
+    Ok(block)
+}
@@ -0,0 +1,128 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::Bsdiff4;
+
+/// Where bytes of the new file come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Original bytes starting at `original_offset`, with diff bytes added.
+    Diff {
+        control: usize,
+        original_offset: u64,
+    },
+    /// Literal bytes of the extra block starting at `extra_offset`.
+    Extra { control: usize, extra_offset: u64 },
+}
+
+/// A run of `len` bytes of the new file starting at `new_offset` that share
+/// one origin, advancing together with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub new_offset: u64,
+    pub len: u64,
+    pub origin: Origin,
+}
+
+impl Bsdiff4 {
+    /// Returns where the byte at `new_offset` of the new file comes from.
+    pub fn provenance(&self, new_offset: u64) -> Option<Origin> {
+        let segments = self.provenance_range(new_offset..new_offset.saturating_add(1));
+
+        segments.first().map(|segment| segment.origin)
+    }
+
+    /// Returns the segments making up `range` of the new file, in order.
+    pub fn provenance_range(&self, range: Range<u64>) -> Vec<Segment> {
+        let mut segments = Vec::new();
+
+        for segment in self.segments() {
+            if segment.new_offset >= range.end {
+                break;
+            }
+
+            segments.extend(clip(segment, segment.new_offset, range.clone()));
+        }
+
+        segments
+    }
+
+    /// Returns the segments of the new file built from bytes in `range` of
+    /// the original, in output order.
+    pub fn dependents(&self, range: Range<u64>) -> Vec<Segment> {
+        self.segments()
+            .filter_map(|segment| match segment.origin {
+                Origin::Diff {
+                    original_offset, ..
+                } => clip(segment, original_offset, range.clone()),
+                Origin::Extra { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Yields the diff and extra segment of every control entry that has one.
+    fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        let mut extra_offset = 0;
+
+        self.controls().flat_map(move |entry| {
+            let diff_len = entry.diff().len() as u64;
+            let extra_len = entry.extra().len() as u64;
+
+            let diff = Segment {
+                new_offset: entry.new_offset(),
+                len: diff_len,
+                origin: Origin::Diff {
+                    control: entry.index(),
+                    original_offset: entry.original_offset(),
+                },
+            };
+            let extra = Segment {
+                new_offset: entry.new_offset() + diff_len,
+                len: extra_len,
+                origin: Origin::Extra {
+                    control: entry.index(),
+                    extra_offset,
+                },
+            };
+
+            extra_offset += extra_len;
+
+            [diff, extra].into_iter().filter(|segment| segment.len > 0)
+        })
+    }
+}
+
+/// Narrows `segment` to where `range` overlaps it, with the segment starting
+/// at `start` in the coordinates of `range`.
+fn clip(segment: Segment, start: u64, range: Range<u64>) -> Option<Segment> {
+    let from = range.start.max(start);
+    let to = range.end.min(start + segment.len);
+
+    if from >= to {
+        return None;
+    }
+
+    let skip = from - start;
+    let origin = match segment.origin {
+        Origin::Diff {
+            control,
+            original_offset,
+        } => Origin::Diff {
+            control,
+            original_offset: original_offset + skip,
+        },
+        Origin::Extra {
+            control,
+            extra_offset,
+        } => Origin::Extra {
+            control,
+            extra_offset: extra_offset + skip,
+        },
+    };
+
+    Some(Segment {
+        new_offset: segment.new_offset + skip,
+        len: to - from,
+        origin,
+    })
+}
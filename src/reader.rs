@@ -0,0 +1,105 @@
+use alloc::vec::Vec;
+
+use itertools::izip;
+
+use crate::io::{self, Read, Seek, SeekFrom};
+use crate::{read_full, Bsdiff4, ControlEntry};
+
+/// A `Read + Seek` view of the new file that patches on demand.
+///
+/// Only the bytes that are read are produced, from the original and the
+/// patch, so a header or a version string can be read without building the
+/// whole new file. Offsets into `original` are from its start.
+pub struct PatchedReader<'a, R> {
+    original: R,
+    index: Vec<ControlEntry<'a>>,
+    new_size: u64,
+    position: u64,
+}
+
+impl<'a, R: Read + Seek> PatchedReader<'a, R> {
+    pub fn new(patch: &'a Bsdiff4, original: R) -> Self {
+        PatchedReader {
+            original,
+            index: patch
+                .controls()
+                .filter(|entry| !entry.diff().is_empty() || !entry.extra().is_empty())
+                .collect(),
+            new_size: patch.new_size() as u64,
+            position: 0,
+        }
+    }
+
+    pub fn new_size(&self) -> u64 {
+        self.new_size
+    }
+
+    pub fn into_inner(self) -> R {
+        self.original
+    }
+}
+
+impl<R: Read + Seek> Read for PatchedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.new_size {
+            return Ok(0);
+        }
+
+        // The entry containing `position`: the last one starting at or before it.
+        let index = self
+            .index
+            .partition_point(|entry| entry.new_offset() <= self.position);
+        let entry = self.index[index - 1];
+        let offset = (self.position - entry.new_offset()) as usize;
+
+        let len = if offset < entry.diff().len() {
+            let diff = &entry.diff()[offset..];
+            let len = diff.len().min(buf.len());
+            let buf = &mut buf[..len];
+
+            self.original
+                .seek(SeekFrom::Start(entry.original_offset() + offset as u64))?;
+
+            if read_full(&mut self.original, buf)? < len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "original is too short for the patch",
+                ));
+            }
+
+            for (new, diff) in izip!(buf, diff) {
+                *new = new.wrapping_add(*diff);
+            }
+
+            len
+        } else {
+            let extra = &entry.extra()[offset - entry.diff().len()..];
+            let len = extra.len().min(buf.len());
+
+            buf[..len].copy_from_slice(&extra[..len]);
+
+            len
+        };
+
+        self.position += len as u64;
+
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for PatchedReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.new_size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        ))?;
+
+        Ok(self.position)
+    }
+}
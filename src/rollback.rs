@@ -0,0 +1,115 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::compose::Composer;
+use crate::io::{Read, Seek, SeekFrom, Write};
+use crate::{Bsdiff4, ControlEntry, Error, Result};
+
+impl Bsdiff4 {
+    /// Applies the patch like [`Bsdiff4::apply`] and returns a patch from the
+    /// new file back to the original.
+    ///
+    /// Original bytes read by diff regions are recovered from the new file, so
+    /// only the bytes the patch discards are stored, in the extra block of the
+    /// returned patch. The original is read up to its end.
+    pub fn apply_with_rollback(
+        &self,
+        original: &mut (impl Read + Seek),
+        new: &mut impl Write,
+    ) -> Result<Bsdiff4> {
+        let start = original.stream_position()?;
+
+        self.apply(original, new)?;
+
+        let original_len = original.seek(SeekFrom::End(0))?.saturating_sub(start);
+        let original_size = usize::try_from(original_len).map_err(|_| Error::LengthOutOfRange {
+            field: "original size",
+            value: original_len,
+        })?;
+
+        let mut runs: Vec<ControlEntry<'_>> = self
+            .controls()
+            .filter(|entry| !entry.diff().is_empty())
+            .collect();
+        runs.sort_by_key(|entry| entry.original_offset());
+
+        let mut composer = Composer::new();
+        let mut offset = 0;
+
+        for entry in runs {
+            let end = entry.original_offset() + entry.diff().len() as u64;
+
+            if end <= offset {
+                continue;
+            }
+
+            let from = entry.original_offset().max(offset);
+
+            if from > offset {
+                push_discarded(original, start + offset, from - offset, &mut composer)?;
+            }
+
+            // new = original + diff, so original = new - diff.
+            let skip = (from - entry.original_offset()) as usize;
+            composer.push_diff(
+                entry.new_offset() + skip as u64,
+                entry.diff()[skip..].iter().map(|diff| diff.wrapping_neg()),
+            );
+
+            offset = end;
+        }
+
+        if original_len > offset {
+            push_discarded(
+                original,
+                start + offset,
+                original_len - offset,
+                &mut composer,
+            )?;
+        }
+
+        let (control, diff, extra) = composer.finish();
+
+        Bsdiff4::from_parts(original_size, control, diff, extra)
+    }
+}
+
+/// Reads `len` original bytes at `offset` into the extra block.
+fn push_discarded<R>(original: &mut R, offset: u64, len: u64, composer: &mut Composer) -> Result<()>
+where
+    R: Read + Seek + ?Sized,
+{
+    let mut bytes = vec![0; len as usize];
+
+    original.seek(SeekFrom::Start(offset))?;
+    original.read_exact(&mut bytes)?;
+
+    composer.push_extra(bytes.into_iter());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::diff::tests::{edit, sample};
+    use crate::io::Cursor;
+    use crate::Bsdiff4;
+
+    #[test]
+    fn rollback_round_trip() {
+        let a = sample(20_000, 8);
+        let b = edit(&a, 9);
+
+        for (a, b) in [(&a, &b), (&b, &a), (&a, &a[..5_000].to_vec())] {
+            let mut new = Vec::new();
+            let rollback = Bsdiff4::diff(a, b)
+                .apply_with_rollback(&mut Cursor::new(a), &mut new)
+                .unwrap();
+
+            assert_eq!(new, *b);
+            assert_eq!(rollback.apply_to_slice(b).unwrap(), *a);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use core::cell::RefCell;
+
+use crate::error::decode_error;
+use crate::io::{self, Read, Seek, SeekFrom, Write};
+use crate::{
+    apply_control, read_control, read_full, read_header, ApplyState, Block, Compression, Control,
+    Error, Header, Result, CONTROL_SIZE,
+};
+
+/// Applies a patch straight from its source without decompressing whole blocks up front.
+///
+/// Like the reference `bspatch`, the control, diff and extra blocks are decoded
+/// by three independent bzip2 decoders positioned at their block offsets.
+/// `ENDSLEY/BSDIFF43` patches are decoded from their single stream.
+pub struct StreamingPatch<R> {
+    patch: R,
+    layout: Layout,
+    new_size: u64,
+}
+
+enum Layout {
+    Blocks {
+        compression: [Compression; 3],
+        control_offset: u64,
+        diff_offset: u64,
+        extra_offset: u64,
+    },
+    Endsley {
+        offset: u64,
+    },
+}
+
+impl<R: Read + Seek> StreamingPatch<R> {
+    pub fn new(mut patch: R) -> Result<Self> {
+        let header = read_header(&mut patch)?;
+        let offset = patch.stream_position()?;
+
+        let layout = match header {
+            Header::Blocks {
+                compression,
+                len_control,
+                len_diff,
+                ..
+            } => {
+                let diff_offset =
+                    offset
+                        .checked_add(len_control)
+                        .ok_or(Error::LengthOutOfRange {
+                            field: "len_control",
+                            value: len_control,
+                        })?;
+                let extra_offset =
+                    diff_offset
+                        .checked_add(len_diff)
+                        .ok_or(Error::LengthOutOfRange {
+                            field: "len_diff",
+                            value: len_diff,
+                        })?;
+
+                Layout::Blocks {
+                    compression,
+                    control_offset: offset,
+                    diff_offset,
+                    extra_offset,
+                }
+            }
+            Header::Endsley { .. } => Layout::Endsley { offset },
+        };
+
+        Ok(StreamingPatch {
+            patch,
+            layout,
+            new_size: header.new_size(),
+        })
+    }
+
+    pub fn new_size(&self) -> u64 {
+        self.new_size
+    }
+
+    pub fn apply(&mut self, original: &mut (impl Read + Seek), new: &mut impl Write) -> Result<()> {
+        let patch = RefCell::new(&mut self.patch);
+        let state = &mut ApplyState::new(original.stream_position()?);
+
+        match self.layout {
+            Layout::Blocks {
+                compression: [control_compression, diff_compression, extra_compression],
+                control_offset,
+                diff_offset,
+                extra_offset,
+            } => {
+                let control = &mut control_compression.decoder(Section::new(
+                    &patch,
+                    control_offset,
+                    Some(diff_offset),
+                ))?;
+                let diff = &mut diff_compression.decoder(Section::new(
+                    &patch,
+                    diff_offset,
+                    Some(extra_offset),
+                ))?;
+                let extra =
+                    &mut extra_compression.decoder(Section::new(&patch, extra_offset, None))?;
+
+                let mut diff_len = 0;
+                let mut extra_len = 0;
+
+                while let Some(control) = next_control(control, state.control, Block::Control)? {
+                    apply_control(&control, original, diff, extra, new, state)?;
+
+                    diff_len += control.diff_amount;
+                    extra_len += control.extra_amount;
+                }
+
+                finish_block(diff, Block::Diff, diff_len)?;
+                finish_block(extra, Block::Extra, extra_len)?;
+            }
+            Layout::Endsley { offset } => {
+                let stream =
+                    RefCell::new(Compression::Bzip2.decoder(Section::new(&patch, offset, None))?);
+
+                while state.new_offset < self.new_size {
+                    let control =
+                        next_control(&mut Shared(&stream), state.control, Block::Interleaved)?
+                            .ok_or(Error::BlockTooShort {
+                                block: Block::Control,
+                                control: state.control,
+                                new_offset: state.new_offset,
+                            })?;
+
+                    apply_control(
+                        &control,
+                        original,
+                        &mut Shared(&stream),
+                        &mut Shared(&stream),
+                        new,
+                        state,
+                    )?;
+                }
+
+                let stream_len = state.control as u64 * CONTROL_SIZE as u64 + state.new_offset;
+                finish_block(&mut Shared(&stream), Block::Interleaved, stream_len)?;
+            }
+        }
+
+        if state.new_offset != self.new_size {
+            return Err(Error::NewSizeMismatch {
+                expected: self.new_size,
+                actual: state.new_offset,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> R {
+        self.patch
+    }
+}
+
+fn next_control(r: &mut impl Read, index: usize, block: Block) -> Result<Option<Control>> {
+    let mut control = [0; CONTROL_SIZE];
+    let filled = read_full(r, &mut control).map_err(|err| decode_error(block, err))?;
+
+    match filled {
+        0 => Ok(None),
+        CONTROL_SIZE => read_control(&mut control.as_slice(), index).map(Some),
+        _ => Err(Error::InvalidControlBlockSize {
+            len: (index * CONTROL_SIZE + filled) as u64,
+        }),
+    }
+}
+
+/// Reads a block to its end, so that the decoder checks the end of its stream,
+/// after `len` bytes of it have been used.
+fn finish_block(r: &mut impl Read, block: Block, len: u64) -> Result<()> {
+    let mut buf = [0; 256];
+    let mut unused = 0;
+
+    loop {
+        match r.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => unused += n as u64,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(decode_error(block, err)),
+        }
+    }
+
+    if unused > 0 {
+        return Err(Error::BlockSizeMismatch {
+            block,
+            expected: len,
+            actual: len + unused,
+        });
+    }
+
+    Ok(())
+}
+
+struct Section<'a, R> {
+    source: &'a RefCell<R>,
+    pos: u64,
+    end: Option<u64>,
+}
+
+impl<'a, R> Section<'a, R> {
+    fn new(source: &'a RefCell<R>, start: u64, end: Option<u64>) -> Self {
+        Section {
+            source,
+            pos: start,
+            end,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for Section<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = match self.end {
+            Some(end) => (end.saturating_sub(self.pos)).min(buf.len() as u64) as usize,
+            None => buf.len(),
+        };
+
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let mut source = self.source.borrow_mut();
+        source.seek(SeekFrom::Start(self.pos))?;
+
+        let n = source.read(&mut buf[..len])?;
+        self.pos += n as u64;
+
+        Ok(n)
+    }
+}
+
+struct Shared<'a, R>(&'a RefCell<R>);
+
+impl<R: Read> Read for Shared<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+#[cfg(all(test, feature = "bzip2"))]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::StreamingPatch;
+    use crate::diff::tests::{edit, sample};
+    use crate::io::Cursor;
+    use crate::{Block, Bsdiff4, Error, Result};
+
+    fn apply(patch: &[u8], original: &[u8]) -> Result<Vec<u8>> {
+        let mut new = Vec::new();
+
+        StreamingPatch::new(Cursor::new(patch))?.apply(&mut Cursor::new(original), &mut new)?;
+
+        Ok(new)
+    }
+
+    fn patches() -> (Vec<u8>, Vec<u8>, [Vec<u8>; 2]) {
+        let old = sample(20_000, 15);
+        let new = edit(&old, 16);
+        let patch = Bsdiff4::diff(&old, &new);
+
+        let mut bsdiff40 = Vec::new();
+        patch.write(&mut bsdiff40).unwrap();
+        let mut endsley = Vec::new();
+        patch.write_endsley(&mut endsley).unwrap();
+
+        (old, new, [bsdiff40, endsley])
+    }
+
+    #[test]
+    fn streaming_round_trip() {
+        let (old, new, patches) = patches();
+
+        for patch in &patches {
+            assert_eq!(apply(patch, &old).unwrap(), new);
+        }
+    }
+
+    #[test]
+    fn streaming_truncated() {
+        let (old, _, patches) = patches();
+
+        for patch in &patches {
+            for cut in 1..=8 {
+                let patch = &patch[..patch.len() - cut];
+
+                assert!(matches!(
+                    apply(patch, &old),
+                    Err(Error::TruncatedBlock { .. })
+                ));
+                assert!(matches!(
+                    Bsdiff4::read(&mut &patch[..]),
+                    Err(Error::TruncatedBlock { .. })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_corrupted() {
+        let (old, _, [bsdiff40, endsley]) = patches();
+
+        let len_control = u64::from_le_bytes(bsdiff40[8..16].try_into().unwrap()) as usize;
+        let len_diff = u64::from_le_bytes(bsdiff40[16..24].try_into().unwrap()) as usize;
+        let extra = 32 + len_control + len_diff;
+
+        let mut corrupted = bsdiff40.clone();
+        corrupted[extra + (bsdiff40.len() - extra) / 2] ^= 0x04;
+
+        assert!(matches!(
+            apply(&corrupted, &old),
+            Err(Error::CorruptBlock {
+                block: Block::Extra,
+                ..
+            })
+        ));
+
+        // Bytes decoded before the bad block checksum may already fail as
+        // controls, so only expect an error of some kind.
+        let mut corrupted = endsley.clone();
+        corrupted[endsley.len() - 20] ^= 0x04;
+
+        assert!(apply(&corrupted, &old).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{Block, Bsdiff4, Error, Result};
+
+impl Bsdiff4 {
+    /// Checks that the control entries agree with the blocks and the header.
+    ///
+    /// Returns the minimum length the original must have for the patch to apply.
+    pub fn validate(&self) -> Result<u64> {
+        let diff_len = self.diff.len() as u64;
+        let extra_len = self.extra.len() as u64;
+
+        let mut diff_offset = 0u64;
+        let mut extra_offset = 0u64;
+        let mut original_offset = 0u64;
+        let mut min_original_len = 0u64;
+
+        for (index, control) in self.control.iter().enumerate() {
+            let new_offset = diff_offset + extra_offset;
+
+            diff_offset = diff_offset
+                .checked_add(control.diff_amount)
+                .filter(|&offset| offset <= diff_len)
+                .ok_or(Error::BlockTooShort {
+                    block: Block::Diff,
+                    control: index,
+                    new_offset,
+                })?;
+
+            extra_offset = extra_offset
+                .checked_add(control.extra_amount)
+                .filter(|&offset| offset <= extra_len)
+                .ok_or(Error::BlockTooShort {
+                    block: Block::Extra,
+                    control: index,
+                    new_offset: new_offset + control.diff_amount,
+                })?;
+
+            if control.diff_amount > 0 {
+                original_offset = original_offset.checked_add(control.diff_amount).ok_or(
+                    Error::LengthOutOfRange {
+                        field: "diff_amount",
+                        value: control.diff_amount,
+                    },
+                )?;
+                min_original_len = min_original_len.max(original_offset);
+            }
+
+            original_offset =
+                original_offset
+                    .checked_add_signed(control.seek)
+                    .ok_or(Error::InvalidSeek {
+                        control: index,
+                        original_offset,
+                        seek: control.seek,
+                    })?;
+        }
+
+        if diff_offset != diff_len {
+            return Err(Error::BlockSizeMismatch {
+                block: Block::Diff,
+                expected: diff_offset,
+                actual: diff_len,
+            });
+        }
+
+        if extra_offset != extra_len {
+            return Err(Error::BlockSizeMismatch {
+                block: Block::Extra,
+                expected: extra_offset,
+                actual: extra_len,
+            });
+        }
+
+        if diff_offset + extra_offset != self.new_size as u64 {
+            return Err(Error::NewSizeMismatch {
+                expected: self.new_size as u64,
+                actual: diff_offset + extra_offset,
+            });
+        }
+
+        Ok(min_original_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::{Block, Bsdiff4, Control, Error};
+
+    #[test]
+    fn validate_min_original_len() {
+        let patch = Bsdiff4::from_parts(
+            135,
+            vec![
+                Control::new(100, 5, 50),
+                Control::new(10, 0, -160),
+                Control::new(20, 0, 0),
+            ],
+            vec![0; 130],
+            vec![0; 5],
+        )
+        .unwrap();
+
+        assert_eq!(patch.validate().unwrap(), 160);
+    }
+
+    #[test]
+    fn validate_block_too_short() {
+        assert!(matches!(
+            Bsdiff4::from_parts(4, vec![Control::new(4, 0, 0)], vec![0; 3], vec![]),
+            Err(Error::BlockTooShort {
+                block: Block::Diff,
+                control: 0,
+                new_offset: 0,
+            })
+        ));
+        assert!(matches!(
+            Bsdiff4::from_parts(
+                5,
+                vec![Control::new(2, 0, 0), Control::new(0, 3, 0)],
+                vec![0; 2],
+                vec![0; 2],
+            ),
+            Err(Error::BlockTooShort {
+                block: Block::Extra,
+                control: 1,
+                new_offset: 2,
+            })
+        ));
+    }
+
+    #[test]
+    fn validate_block_size_mismatch() {
+        assert!(matches!(
+            Bsdiff4::from_parts(2, vec![Control::new(2, 0, 0)], vec![0; 3], vec![]),
+            Err(Error::BlockSizeMismatch {
+                block: Block::Diff,
+                expected: 2,
+                actual: 3,
+            })
+        ));
+        assert!(matches!(
+            Bsdiff4::from_parts(2, vec![Control::new(0, 2, 0)], vec![], vec![0; 4]),
+            Err(Error::BlockSizeMismatch {
+                block: Block::Extra,
+                expected: 2,
+                actual: 4,
+            })
+        ));
+    }
+
+    #[test]
+    fn validate_new_size_mismatch() {
+        assert!(matches!(
+            Bsdiff4::from_parts(4, vec![Control::new(2, 1, 0)], vec![0; 2], vec![0; 1]),
+            Err(Error::NewSizeMismatch {
+                expected: 4,
+                actual: 3,
+            })
+        ));
+    }
+
+    #[test]
+    fn validate_invalid_seek() {
+        assert!(matches!(
+            Bsdiff4::from_parts(2, vec![Control::new(2, 0, -3)], vec![0; 2], vec![]),
+            Err(Error::InvalidSeek {
+                control: 0,
+                original_offset: 2,
+                seek: -3,
+            })
+        ));
+    }
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "std")]
+
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+use bsdiff4_rs::{Bsdiff4, Compression};
+
+fn sample(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn patch_old_file_in_place() {
+    let dir = env::temp_dir().join(format!("bspatch-test-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let old = sample(10_000, 1);
+    let mut new = old.clone();
+    new[2_000..2_500].copy_from_slice(&sample(500, 2));
+    new.extend_from_slice(b"appended");
+
+    let mut patch = Vec::new();
+    Bsdiff4::diff(&old, &new)
+        .write_compressed(&mut patch, Compression::Raw, None)
+        .unwrap();
+
+    let file = dir.join("file");
+    let patch_file = dir.join("patch");
+    fs::write(&file, &old).unwrap();
+    fs::write(&patch_file, &patch).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_bspatch"))
+        .arg(&file)
+        .arg(&file)
+        .arg(&patch_file)
+        .status()
+        .unwrap();
+
+    let patched = fs::read(&file).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(status.success());
+    assert_eq!(patched, new);
+}
@@ -0,0 +1,79 @@
+//! Patches written by other encoders, laid out by `fixtures/generate.py`.
+
+#![cfg(any(feature = "bzip2", feature = "brotli"))]
+
+use std::io::Cursor;
+
+use bsdiff4_rs::{Bsdiff4, Error, Event, PatchDecoder, Result, StreamingPatch};
+
+const OLD: &[u8] = include_bytes!("fixtures/old");
+const NEW: &[u8] = include_bytes!("fixtures/new");
+
+fn read(patch: &[u8]) -> Result<Vec<u8>> {
+    Bsdiff4::read(&mut &patch[..])?.apply_to_slice(OLD)
+}
+
+fn stream(patch: &[u8]) -> Result<Vec<u8>> {
+    let mut new = Vec::new();
+
+    StreamingPatch::new(Cursor::new(patch))?.apply(&mut Cursor::new(OLD), &mut new)?;
+
+    Ok(new)
+}
+
+fn decode(patch: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = PatchDecoder::new();
+    let mut input = patch.chunks(16);
+    let mut new = Vec::new();
+
+    loop {
+        match decoder.poll()? {
+            Event::NeedInput => decoder.push(input.next().expect("patch is complete")),
+            Event::NeedOriginal { offset, len } => {
+                let offset = offset as usize;
+                decoder.supply_original(&OLD[offset..offset + len])?;
+            }
+            Event::Control(_) => {}
+            Event::Output(bytes) => new.extend_from_slice(bytes),
+            Event::Done => return Ok(new),
+        }
+    }
+}
+
+#[cfg(feature = "bzip2")]
+#[test]
+fn endsley() {
+    let patch = include_bytes!("fixtures/endsley.patch");
+
+    assert_eq!(read(patch).unwrap(), NEW);
+    assert_eq!(stream(patch).unwrap(), NEW);
+    assert_eq!(decode(patch).unwrap(), NEW);
+}
+
+#[test]
+fn endsley_wrong_magic() {
+    let mut patch = include_bytes!("fixtures/endsley.patch").to_vec();
+    patch[8..16].copy_from_slice(b"BSDIFF44");
+
+    assert!(matches!(read(&patch), Err(Error::InvalidMagic)));
+    assert!(matches!(stream(&patch), Err(Error::InvalidMagic)));
+    assert!(matches!(decode(&patch), Err(Error::InvalidMagic)));
+}
+
+#[cfg(feature = "bzip2")]
+#[test]
+fn bsdf2_bzip2() {
+    let patch = include_bytes!("fixtures/bsdf2-bzip2.patch");
+
+    assert_eq!(read(patch).unwrap(), NEW);
+    assert_eq!(stream(patch).unwrap(), NEW);
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn bsdf2_brotli() {
+    let patch = include_bytes!("fixtures/bsdf2-brotli.patch");
+
+    assert_eq!(read(patch).unwrap(), NEW);
+    assert_eq!(stream(patch).unwrap(), NEW);
+}
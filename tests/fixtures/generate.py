@@ -0,0 +1,118 @@
+#!/usr/bin/env python3
+"""Writes the patch fixtures next to this script.
+
+The patches are laid out by hand following the published formats, not with
+this crate's writer, so that the tests check the readers against independent
+encoders:
+
+- `endsley.patch`: mendsley/bsdiff's `ENDSLEY/BSDIFF43`, one bzip2 stream of
+  interleaved control, diff and extra data.
+- `bsdf2-bzip2.patch` and `bsdf2-brotli.patch`: Android's `BSDF2`, with the
+  three blocks compressed separately by the type named in the header.
+
+bzip2 streams come from libbz2 through Python's `bz2` at level 9, as the
+reference tools use. Brotli streams are built from uncompressed meta-blocks.
+"""
+
+import bz2
+import os
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+def offtout(x):
+    """Sign-magnitude little-endian 64-bit integer."""
+    if x < 0:
+        return ((-x) | (1 << 63)).to_bytes(8, "little")
+    return x.to_bytes(8, "little")
+
+
+def brotli(data):
+    """A valid brotli stream holding `data` in uncompressed meta-blocks."""
+    bits = []
+
+    def put(value, n):
+        bits.extend((value >> i) & 1 for i in range(n))
+
+    def align():
+        while len(bits) % 8:
+            bits.append(0)
+
+    def flush():
+        out = bytearray()
+        for i in range(0, len(bits), 8):
+            out.append(sum(bit << j for j, bit in enumerate(bits[i : i + 8])))
+        bits.clear()
+        return bytes(out)
+
+    out = bytearray()
+    put(0, 1)  # WBITS = 16
+
+    for start in range(0, len(data), 1 << 16):
+        chunk = data[start : start + (1 << 16)]
+        put(0, 1)  # ISLAST
+        put(0, 2)  # MNIBBLES = 4
+        put(len(chunk) - 1, 16)  # MLEN - 1
+        put(1, 1)  # ISUNCOMPRESSED
+        align()
+        out += flush() + chunk
+
+    put(1, 1)  # ISLAST
+    put(1, 1)  # ISLASTEMPTY
+    align()
+    out += flush()
+
+    return bytes(out)
+
+
+old = b"".join(
+    b"line %03d: the quick brown fox jumps over the lazy dog\n" % i for i in range(40)
+)
+line = len(old) // 40
+
+# (original offset, diff length, extra bytes), with the diff region of the
+# second entry changing "fox" to "FOX" and the third seeking backwards.
+entries = [
+    (0, 10 * line, b"an inserted line\n"),
+    (13 * line, 18 * line, b""),
+    (5 * line, 2 * line, b"and a trailer\n"),
+]
+
+controls, diff, extra, new = [], bytearray(), bytearray(), bytearray()
+
+for i, (offset, length, added) in enumerate(entries):
+    source = old[offset : offset + length]
+    target = source.replace(b"fox", b"FOX") if i == 1 else source
+    diff_bytes = bytes((t - s) & 0xFF for s, t in zip(source, target))
+
+    if i + 1 < len(entries):
+        seek = entries[i + 1][0] - (offset + length)
+    else:
+        seek = 0
+
+    controls.append((offtout(length) + offtout(len(added)) + offtout(seek), diff_bytes, added))
+    diff += diff_bytes
+    extra += added
+    new += target + added
+
+control_block = b"".join(c for c, _, _ in controls)
+interleaved = b"".join(c + d + e for c, d, e in controls)
+
+
+def write(name, data):
+    with open(os.path.join(HERE, name), "wb") as f:
+        f.write(data)
+
+
+def bsdf2(compression, compress):
+    blocks = [compress(control_block), compress(bytes(diff)), compress(bytes(extra))]
+    header = b"BSDF2" + bytes([compression] * 3)
+    header += offtout(len(blocks[0])) + offtout(len(blocks[1])) + offtout(len(new))
+    return header + b"".join(blocks)
+
+
+write("old", old)
+write("new", bytes(new))
+write("endsley.patch", b"ENDSLEY/BSDIFF43" + offtout(len(new)) + bz2.compress(interleaved, 9))
+write("bsdf2-bzip2.patch", bsdf2(1, lambda data: bz2.compress(data, 9)))
+write("bsdf2-brotli.patch", bsdf2(2, brotli))